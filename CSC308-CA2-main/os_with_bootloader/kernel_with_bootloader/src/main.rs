@@ -2,8 +2,7 @@
 #![no_main]
 use bootloader_api::config::Mapping;
 use x86_64::instructions::hlt;
-mod writer;
-use writer::FrameBufferWriter;
+use kernel_with_bootloader::{println, writer};
 
 //Use the entry_point macro to register the entry point function: 
 //bootloader_api::entry_point!(kernel_main)
@@ -20,9 +19,10 @@ bootloader_api::entry_point!(my_entry_point, config =
 fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
 
     let frame_buffer_info = boot_info.framebuffer.as_mut().unwrap().info();
-    let buffer = boot_info.framebuffer.as_mut().unwrap().buffer_mut(); let mut frame_buffer_writer =
-    FrameBufferWriter::new(buffer, frame_buffer_info); use core::fmt::Write;//below requires this
-    writeln!(frame_buffer_writer, "Hello, world!\nThis is a test.\ncBlue Text\tIndented Text").unwrap();
+    let buffer = boot_info.framebuffer.as_mut().unwrap().buffer_mut();
+    writer::init(buffer, frame_buffer_info);
+    println!("Hello, world!\nThis is a test.");
+    println!("\x1b[34mBlue Text\x1b[0m\tIndented Text");
     
     loop {
      hlt();//stop x86_64 from being unnecessarily busy while looping
@@ -32,7 +32,8 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
     }
 
 #[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    writer::print_panic(info);
     loop {
      hlt();
     }
@@ -1,162 +1,3383 @@
-mod constants;
+pub mod constants;
+mod bmp;
+mod cell;
+pub mod colors;
+mod compositor;
+mod damage;
+mod escape;
+mod graphics;
+mod line_buffered;
+mod logger;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod orientation;
+mod palette;
+mod ppm;
+mod psf;
+mod scrollback;
+mod sprite;
+mod sync;
+mod text_area;
+mod vga;
+pub use bmp::{decode_bmp, BmpError};
+pub use cell::{Cell, TextBuffer, TextPosition, BUILTIN_TEXT_BUFFERS, LINE_STAGING_LEN, MAX_COLUMNS, MAX_ROWS};
+pub use colors::{rgb, Color};
+pub use compositor::{Compositor, DrawLayer, MAX_LAYERS};
+pub use graphics::WrongPixelCount;
+pub use line_buffered::{LineBuffered, LINE_BUFFER_LEN};
+pub use logger::{init_logger, LockedWriter};
+pub use orientation::Orientation;
+pub use palette::{palette_color, PALETTE_256};
+pub use psf::{PsfError, PsfFont};
+pub use scrollback::SCROLLBACK_LINES;
+pub use sprite::{MAX_SPRITE_SIZE, SPRITE_TRANSPARENT};
+pub use sync::SyncWriter;
+pub use vga::{vga_attr_colors, VgaCompat, VGA_COLORS, VGA_COLUMNS, VGA_ROWS};
 use core::{
     fmt::{self, Write},
+    ops::Deref,
     ptr,
 };
 use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+use cell::{claim_builtin_text, Row};
+use scrollback::Scrollback;
+use sprite::SpriteBackup;
 use constants::font_constants;
+use escape::{parse_extended_color, CsiSequence, EscapeParser, Parsed};
 use constants::font_constants::{BACKUP_CHAR, CHAR_RASTER_HEIGHT, FONT_WEIGHT};
 use noto_sans_mono_bitmap::{get_raster, RasterizedChar};
+use spin::{Mutex, MutexGuard};
 
 const LINE_SPACING: usize = 2;
 const LETTER_SPACING: usize = 0;
 const BORDER_PADDING: usize = 1;
 
-/// Returns the raster of the given char or the raster of [font_constants::BACKUP_CHAR].
-fn get_char_raster(c: char) -> RasterizedChar {
-    get_raster(c, FONT_WEIGHT, CHAR_RASTER_HEIGHT)
-        .unwrap_or_else(|| get_raster(BACKUP_CHAR, FONT_WEIGHT, CHAR_RASTER_HEIGHT)
-            .expect("Should get raster of backup char."))
+/// Number of char widths between two tab stops.
+const DEFAULT_TAB_SIZE: usize = 4;
+
+const DEFAULT_TEXT_COLOR: [u8; 3] = [255, 255, 255];
+const DEFAULT_BG_COLOR: [u8; 3] = [0, 0, 0];
+const PANIC_BG_COLOR: [u8; 3] = [0, 0, 170];
+
+/// First and number of chars in [ASCII_RASTERS], the printable ASCII range.
+const FIRST_CACHED_CHAR: u32 = 0x20;
+const CACHED_CHARS: usize = 0x7f - FIRST_CACHED_CHAR as usize;
+
+/// The raster of every printable ASCII char, or of the backup char for those
+/// the font lacks. Looked up once at compile time, so the common chars don't
+/// go through [get_raster] each time they're drawn. None where even the
+/// backup char is missing.
+static ASCII_RASTERS: [Option<RasterizedChar>; CACHED_CHARS] = {
+    let mut rasters = [const { None }; CACHED_CHARS];
+    let mut i = 0;
+    while i < CACHED_CHARS {
+        let c = (FIRST_CACHED_CHAR as u8 + i as u8) as char;
+        rasters[i] = match get_raster(c, FONT_WEIGHT, CHAR_RASTER_HEIGHT) {
+            Some(raster) => Some(raster),
+            None => get_raster(BACKUP_CHAR, FONT_WEIGHT, CHAR_RASTER_HEIGHT),
+        };
+        i += 1;
+    }
+    rasters
+};
+
+/// A glyph raster, either from [ASCII_RASTERS] or looked up on the spot.
+enum Raster {
+    Cached(&'static RasterizedChar),
+    Uncached(RasterizedChar),
+}
+
+impl Deref for Raster {
+    type Target = RasterizedChar;
+
+    fn deref(&self) -> &RasterizedChar {
+        match self {
+            Raster::Cached(raster) => raster,
+            Raster::Uncached(raster) => raster,
+        }
+    }
+}
+
+/// The pixels of one char, from the built-in font or a PSF font.
+#[derive(Clone, Copy)]
+enum Glyph<'g> {
+    Raster(&'g RasterizedChar),
+    /// One bit per pixel, most significant first, each row padded to a byte.
+    Bitmap { bits: &'g [u8], width: usize, height: usize },
+    /// A filled box, drawn when the font has nothing to show.
+    Solid { width: usize, height: usize },
+}
+
+impl Glyph<'_> {
+    fn width(&self) -> usize {
+        match self {
+            Glyph::Raster(raster) => raster.width(),
+            Glyph::Bitmap { width, .. } | Glyph::Solid { width, .. } => *width,
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            Glyph::Raster(raster) => raster.height(),
+            Glyph::Bitmap { height, .. } | Glyph::Solid { height, .. } => *height,
+        }
+    }
+
+    /// How much of pixel (x, y) the glyph covers, from 0 to 255.
+    fn intensity(&self, x: usize, y: usize) -> u8 {
+        match self {
+            Glyph::Raster(raster) => raster.raster()[y][x],
+            Glyph::Bitmap { bits, width, .. } => {
+                let byte = bits[y * width.div_ceil(8) + x / 8];
+                if byte & (0x80 >> (x % 8)) != 0 { 255 } else { 0 }
+            }
+            Glyph::Solid { .. } => 255,
+        }
+    }
+}
+
+/// Returns the raster of the given char or the raster of [font_constants::BACKUP_CHAR],
+/// or None if the font has neither.
+fn get_char_raster(c: char) -> Option<Raster> {
+    let index = (c as u32).wrapping_sub(FIRST_CACHED_CHAR) as usize;
+    match ASCII_RASTERS.get(index) {
+        Some(raster) => raster.as_ref().map(Raster::Cached),
+        None => get_raster(c, FONT_WEIGHT, CHAR_RASTER_HEIGHT)
+            .or_else(|| get_raster(BACKUP_CHAR, FONT_WEIGHT, CHAR_RASTER_HEIGHT))
+            .map(Raster::Uncached),
+    }
+}
+
+/// Whether `c` is a zero-width combining mark, e.g. U+0301 COMBINING ACUTE
+/// ACCENT. Neither font can stack it onto the char before, so it's skipped
+/// rather than taking up a cell of its own. Covers the combining blocks,
+/// not every nonspacing mark in Unicode.
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0300..=0x036f
+            | 0x0483..=0x0489
+            | 0x0591..=0x05bd
+            | 0x0610..=0x061a
+            | 0x064b..=0x065f
+            | 0x1ab0..=0x1aff
+            | 0x1dc0..=0x1dff
+            | 0x20d0..=0x20ff
+            | 0xfe20..=0xfe2f
+    )
+}
+
+/// The built-in font's glyph for a raster from [get_char_raster], or a
+/// solid box where there is none.
+fn builtin_glyph(raster: Option<&RasterizedChar>) -> Glyph<'_> {
+    match raster {
+        Some(raster) => Glyph::Raster(raster),
+        None => Glyph::Solid { width: font_constants::CHAR_RASTER_WIDTH, height: CHAR_RASTER_HEIGHT.val() },
+    }
+}
+
+/// Mixes a background and a foreground channel by a glyph coverage value, so
+/// antialiased edge pixels come out as in-between shades.
+fn blend_channel(background: u8, foreground: u8, intensity: u8) -> u8 {
+    // The weights add up to 255, so the sum stays below 255 * 255 and the
+    // cast back can't truncate.
+    let intensity = intensity as u16;
+    ((background as u16 * (255 - intensity) + foreground as u16 * intensity) / 255) as u8
+}
+
+/// Perceived brightness of a color, i.e. 0.299 R + 0.587 G + 0.114 B with
+/// the weights rounded to 256ths.
+fn luminance([r, g, b]: [u8; 3]) -> u8 {
+    ((r as u16 * 77 + g as u16 * 150 + b as u16 * 29) >> 8) as u8
+}
+
+/// Scales a channel by `factor / 255`, so 255 leaves it unchanged.
+fn scale_channel(value: u8, factor: u8) -> u8 {
+    (value as u16 * factor as u16 / 255) as u8
+}
+
+/// Scales the channels of one already converted pixel in place.
+fn dim_pixel(pixel: &mut [u8], format: PixelFormat, factor: u8) {
+    match format {
+        PixelFormat::Unknown { red_position, green_position, blue_position } if pixel.len() > 2 => {
+            let mut bytes = [0; 4];
+            bytes[..pixel.len()].copy_from_slice(pixel);
+            let mut value = u32::from_le_bytes(bytes);
+            for position in [red_position, green_position, blue_position] {
+                let channel = scale_channel((value >> position) as u8, factor);
+                value = value & !(0xff << position) | (channel as u32) << position;
+            }
+            pixel.copy_from_slice(&value.to_le_bytes()[..pixel.len()]);
+        }
+        _ if pixel.len() == 2 && format != PixelFormat::U8 => {
+            let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+            // Each field is scaled in its own width; the results stay in range.
+            let scale = |field: u16| field * factor as u16 / 255;
+            let packed = scale(value >> 11) << 11 | scale(value >> 5 & 0x3f) << 5 | scale(value & 0x1f);
+            pixel.copy_from_slice(&packed.to_le_bytes());
+        }
+        _ => pixel.iter_mut().for_each(|byte| *byte = scale_channel(*byte, factor)),
+    }
+}
+
+/// Bytes a buffer needs so that every visible pixel of `info` lies inside it.
+fn required_len(info: &FrameBufferInfo) -> usize {
+    if info.width == 0 || info.height == 0 {
+        return 0;
+    }
+    ((info.height - 1) * info.stride + info.width) * info.bytes_per_pixel
+}
+
+/// Writes the digits of `n` in the given radix, lowercase, into the end of
+/// `buffer` and returns them. 20 digits are enough for any u64 in base 10 or
+/// higher.
+fn format_digits(mut n: u64, radix: u64, buffer: &mut [u8; 20]) -> &[u8] {
+    let mut start = buffer.len();
+    loop {
+        start -= 1;
+        buffer[start] = b"0123456789abcdef"[(n % radix) as usize];
+        n /= radix;
+        if n == 0 {
+            return &buffer[start..];
+        }
+    }
+}
+
+/// Whether the writer can draw into a framebuffer with this layout.
+fn is_supported(info: &FrameBufferInfo) -> bool {
+    match info.pixel_format {
+        PixelFormat::Rgb | PixelFormat::Bgr | PixelFormat::U8 => true,
+        // 16 bpp RGB565, reported by some firmware as explicit bit positions
+        PixelFormat::Unknown { red_position: 11, green_position: 5, blue_position: 0 }
+            if info.bytes_per_pixel == 2 =>
+        {
+            true
+        }
+        // 8 bits per channel at arbitrary positions, as long as they fit
+        PixelFormat::Unknown { red_position, green_position, blue_position } => {
+            let bits = info.bytes_per_pixel * 8;
+            (3..=4).contains(&info.bytes_per_pixel)
+                && [red_position, green_position, blue_position]
+                    .iter()
+                    .all(|&position| position as usize + 8 <= bits)
+        }
+        _ => false,
+    }
+}
+
+/// What the panicking constructors make of a failed [FrameBufferWriter::try_new].
+fn unwrap_new(writer: Result<FrameBufferWriter, WriterError>) -> FrameBufferWriter {
+    match writer {
+        Ok(writer) => writer,
+        Err(WriterError::NoTextBuffer) => panic!("A built-in text buffer should be free."),
+        Err(_) => panic!("Framebuffer pixel format should be supported."),
+    }
+}
+
+/// A built-in text buffer for the constructors that don't take one.
+///
+/// Panics if they are all taken.
+fn builtin_text() -> &'static mut TextBuffer {
+    claim_builtin_text().expect("A built-in text buffer should be free.")
+}
+
+/// Shape of the text cursor drawn by [FrameBufferWriter::draw_cursor].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    /// Fills the whole character cell.
+    Block,
+    /// A thin line along the bottom of the cell.
+    Underline,
+    /// A thin line along the left edge of the cell.
+    Bar,
+}
+
+/// Thickness in pixels of the underline and bar cursors.
+const CURSOR_THICKNESS: usize = 2;
+
+/// How many chars of a word are held back in [WrapMode::Word] before it gets
+/// drawn anyway.
+const WORD_BUFFER_LEN: usize = 64;
+
+bitflags::bitflags! {
+    /// Decorations drawn on top of each glyph.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TextAttrs: u8 {
+        /// A line along the bottom of the char cell.
+        const UNDERLINE = 1 << 0;
+        /// A line through the middle of the char cell.
+        const STRIKETHROUGH = 1 << 1;
+        /// Shown and hidden in turn by [FrameBufferWriter::tick].
+        const BLINK = 1 << 2;
+    }
+}
+
+/// VT100-style double-size text, stretched on top of the scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeMode {
+    Normal,
+    DoubleWidth,
+    DoubleHeight,
+    DoubleBoth,
+}
+
+impl SizeMode {
+    /// How much wider and higher than normal chars are drawn.
+    fn factors(self) -> (usize, usize) {
+        match self {
+            SizeMode::Normal => (1, 1),
+            SizeMode::DoubleWidth => (2, 1),
+            SizeMode::DoubleHeight => (1, 2),
+            SizeMode::DoubleBoth => (2, 2),
+        }
+    }
+}
+
+/// Drawn behind each glyph to keep text readable on busy backgrounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEffect {
+    None,
+    /// The glyph again in the given color, offset by (dx, dy) pixels.
+    Shadow([u8; 3], isize, isize),
+    /// The glyph again in the given color, offset by 1px in all 8 directions.
+    Outline([u8; 3]),
+}
+
+/// Where lines that don't fit on the screen are broken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Break right before the first char that doesn't fit.
+    Char,
+    /// Move a word that doesn't fit to the next line as a whole. Words
+    /// longer than a line are still broken like in [WrapMode::Char].
+    Word,
+}
+
+/// Which way the chars of a line follow each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextDirection {
+    /// From the left edge to the right.
+    Ltr,
+    /// From the right edge to the left, e.g. for right-to-left scripts.
+    Rtl,
 }
 
 /// Allows logging text to a pixel-based framebuffer.
-pub struct FrameBufferWriter {
-    framebuffer: &'static mut [u8],
+///
+/// The framebuffer is borrowed for `'a`, so a writer can also draw into a
+/// plain local buffer; the global writer uses [StaticFrameBufferWriter].
+/// Drawing needs `&mut self`, so a writer shared between several users has
+/// to sit behind a lock like [SyncWriter].
+pub struct FrameBufferWriter<'a> {
+    framebuffer: &'a mut [u8],
+    back_buffer: Option<&'a mut [u8]>,
     info: FrameBufferInfo,
     x_pos: usize,
     y_pos: usize,
     text_color: [u8; 3],
+    bg_color: [u8; 3],
+    /// What [FrameBufferWriter::reset_colors] and `ESC [ 0 m` go back to.
+    default_colors: ([u8; 3], [u8; 3]),
+    opaque_background: bool,
+    pending_escape: EscapeParser,
+    cursor_visible: bool,
+    cursor_shape: CursorShape,
+    saved_cursor: Option<(usize, usize)>,
+    wrap_mode: WrapMode,
+    /// Whether text that runs past the right edge continues on the next line
+    /// rather than being dropped.
+    autowrap: bool,
+    /// Set once a char fills the last column. Like a VT terminal, the line
+    /// only wraps when the next printable char arrives, and self.x_pos stays
+    /// on the last cell until then.
+    wrap_pending: bool,
+    /// In [TextDirection::Rtl], self.x_pos is the right edge of the next char
+    /// instead of its left edge.
+    direction: TextDirection,
+    word: [char; WORD_BUFFER_LEN],
+    word_len: usize,
+    tab_size: usize,
+    line_spacing: usize,
+    letter_spacing: usize,
+    /// When set, every char takes up exactly this many pixels on the line,
+    /// letter spacing included, whatever its glyph's width.
+    fixed_advance: Option<usize>,
+    border_padding: usize,
+    scale: usize,
+    size_mode: SizeMode,
+    text_effect: TextEffect,
+    text_attrs: TextAttrs,
+    reverse: bool,
+    previous_line_end: Option<usize>,
+    scrollback: &'a mut Scrollback,
+    cells: &'a mut [Row; MAX_ROWS],
+    line_staging: &'a mut [u8; LINE_STAGING_LEN],
+    /// The bytes of the screen buffers [FrameBufferWriter::write_line] is
+    /// staging in `line_staging`, as a range.
+    staged: Option<(usize, usize)>,
+    /// Whether blinking cells are currently shown, flipped by each tick.
+    blink_visible: bool,
+    /// The highlighted cells, as the first and last (column, row).
+    highlight: Option<((usize, usize), (usize, usize))>,
+    scroll_offset: usize,
+    record_history: bool,
+    brightness: u8,
+    /// The part of the screen the text is kept in, as (x, y, w, h), if not
+    /// the whole screen.
+    text_area: Option<(usize, usize, usize, usize)>,
+    /// Text rows `top..bottom` that scroll, if not the whole screen.
+    scroll_region: Option<(usize, usize)>,
+    /// Pixels drawn since the last flush_damage, as (x0, y0, x1, y1).
+    dirty: Option<(usize, usize, usize, usize)>,
+    orientation: Orientation,
+    sprite_backup: SpriteBackup,
+    font: Option<PsfFont<'a>>,
+}
+
+/// A writer over the framebuffer handed over by the bootloader, which lives
+/// for the rest of the kernel's run.
+pub type StaticFrameBufferWriter = FrameBufferWriter<'static>;
+
+/// Why a fallible writer operation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriterError {
+    /// The framebuffer's pixel format can't be drawn in.
+    UnsupportedPixelFormat(PixelFormat),
+    /// The font has no glyph for the char.
+    MissingGlyph(char),
+    /// There's no room left on the screen to draw at.
+    OutOfBounds,
+    /// There's no back buffer to stage the drawing in.
+    NoBackBuffer,
+    /// All of the [BUILTIN_TEXT_BUFFERS] are taken; lend the writer a
+    /// [TextBuffer] with [FrameBufferWriter::new_in] instead.
+    NoTextBuffer,
 }
 
-impl FrameBufferWriter {
-    pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+impl<'a> FrameBufferWriter<'a> {
+    /// Creates a writer and clears the screen. The text written is kept in
+    /// one of the [BUILTIN_TEXT_BUFFERS], which stays taken for good; once
+    /// they are all gone, use [Self::new_in].
+    ///
+    /// Panics if the pixel format is unsupported or no built-in text buffer
+    /// is left; see [Self::try_new].
+    pub fn new(framebuffer: &'a mut [u8], info: FrameBufferInfo) -> Self {
+        unwrap_new(Self::try_new(framebuffer, info))
+    }
+
+    /// Creates a writer and clears the screen, or fails without touching the
+    /// framebuffer if its pixel format can't be drawn in: Rgb, Bgr, U8,
+    /// 16-bit RGB565 and `Unknown` formats with 8-bit channels are supported.
+    /// Also fails if all of the [BUILTIN_TEXT_BUFFERS] are taken.
+    ///
+    /// With the `unchecked` feature, pixel writes skip their bounds checks,
+    /// so the framebuffer must then hold every pixel `info` describes; this
+    /// panics if it is too short.
+    pub fn try_new(framebuffer: &'a mut [u8], info: FrameBufferInfo) -> Result<Self, WriterError> {
+        // Checked first so that a writer that can't be made doesn't use up a
+        // text buffer.
+        if !is_supported(&info) {
+            return Err(WriterError::UnsupportedPixelFormat(info.pixel_format));
+        }
+        let text = claim_builtin_text().ok_or(WriterError::NoTextBuffer)?;
+        Self::try_new_in(framebuffer, info, text)
+    }
+
+    /// Like [Self::new], but the text written is kept in `text`, whose old
+    /// contents are dropped.
+    ///
+    /// Panics if the pixel format is unsupported; see [Self::try_new_in].
+    pub fn new_in(framebuffer: &'a mut [u8], info: FrameBufferInfo, text: &'a mut TextBuffer) -> Self {
+        unwrap_new(Self::try_new_in(framebuffer, info, text))
+    }
+
+    /// Like [Self::try_new], but the text written is kept in `text`, so it
+    /// never runs out of text buffers.
+    pub fn try_new_in(
+        framebuffer: &'a mut [u8],
+        info: FrameBufferInfo,
+        text: &'a mut TextBuffer,
+    ) -> Result<Self, WriterError> {
+        if !is_supported(&info) {
+            return Err(WriterError::UnsupportedPixelFormat(info.pixel_format));
+        }
+        // Pixel writes skip their bounds checks in this build, so they rely
+        // on this.
+        #[cfg(feature = "unchecked")]
+        assert!(framebuffer.len() >= required_len(&info), "Framebuffer should hold every pixel.");
+        let TextBuffer { cells, scrollback, line_staging } = text;
+        scrollback.clear();
         let mut logger = Self {
             framebuffer,
+            back_buffer: None,
             info,
             x_pos: BORDER_PADDING,
             y_pos: BORDER_PADDING,
-            text_color: [255, 255, 255],
+            text_color: DEFAULT_TEXT_COLOR,
+            bg_color: DEFAULT_BG_COLOR,
+            default_colors: (DEFAULT_TEXT_COLOR, DEFAULT_BG_COLOR),
+            opaque_background: true,
+            pending_escape: EscapeParser::new(),
+            cursor_visible: false,
+            cursor_shape: CursorShape::Block,
+            saved_cursor: None,
+            wrap_mode: WrapMode::Char,
+            autowrap: true,
+            wrap_pending: false,
+            direction: TextDirection::Ltr,
+            word: ['\0'; WORD_BUFFER_LEN],
+            word_len: 0,
+            tab_size: DEFAULT_TAB_SIZE,
+            line_spacing: LINE_SPACING,
+            letter_spacing: LETTER_SPACING,
+            fixed_advance: None,
+            border_padding: BORDER_PADDING,
+            scale: 1,
+            size_mode: SizeMode::Normal,
+            text_effect: TextEffect::None,
+            text_attrs: TextAttrs::empty(),
+            reverse: false,
+            previous_line_end: None,
+            scrollback,
+            cells,
+            line_staging,
+            staged: None,
+            blink_visible: true,
+            highlight: None,
+            scroll_offset: 0,
+            record_history: true,
+            brightness: 255,
+            text_area: None,
+            scroll_region: None,
+            dirty: None,
+            orientation: Orientation::Normal,
+            sprite_backup: SpriteBackup::new(),
+            font: None,
         };
         logger.clear(); // Reset framebuffer at initialization
-        logger
+        Ok(logger)
     }
 
-    pub fn set_text_color(&mut self, color: [u8; 3]) {
-        self.text_color = color;
+    /// Like [Self::new], but all drawing goes to `back_buffer` and only shows
+    /// up on screen once [Self::present] is called. The back buffer should be
+    /// the same size as the framebuffer.
+    pub fn with_back_buffer(framebuffer: &'a mut [u8], back_buffer: &'a mut [u8], info: FrameBufferInfo) -> Self {
+        Self::with_back_buffer_in(framebuffer, back_buffer, info, builtin_text())
     }
 
-    fn newline(&mut self) {
-        self.y_pos += font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
-        self.carriage_return();
+    /// Like [Self::with_back_buffer], but keeping the text in `text`, see
+    /// [Self::new_in].
+    pub fn with_back_buffer_in(
+        framebuffer: &'a mut [u8],
+        back_buffer: &'a mut [u8],
+        info: FrameBufferInfo,
+        text: &'a mut TextBuffer,
+    ) -> Self {
+        #[cfg(feature = "unchecked")]
+        assert!(back_buffer.len() >= required_len(&info), "Back buffer should hold every pixel.");
+        let mut logger = Self::new_in(framebuffer, info, text);
+        logger.back_buffer = Some(back_buffer);
+        logger.clear();
+        logger
     }
 
-    fn carriage_return(&mut self) {
-        self.x_pos = BORDER_PADDING;
+    /// Switches over to a new framebuffer, e.g. after a mode change, and
+    /// redraws the text on it reflowed to the new size: lines wider than the
+    /// screen wrap, and lines that no longer fit drop off the top. The back
+    /// buffer, scroll region, saved cursor and cursor sprite are dropped.
+    ///
+    /// Panics if the pixel format is unsupported, like [Self::new].
+    pub fn reinit(&mut self, framebuffer: &'a mut [u8], info: FrameBufferInfo) {
+        assert!(is_supported(&info), "Framebuffer pixel format should be supported.");
+        #[cfg(feature = "unchecked")]
+        assert!(framebuffer.len() >= required_len(&info), "Framebuffer should hold every pixel.");
+        self.flush_word();
+        self.scroll_to_live();
+        // With a wrap pending the cursor is already past the last char.
+        let (column, row) = self.cursor_cell();
+        let cursor = (column + self.wrap_pending as usize, row);
+        self.framebuffer = framebuffer;
+        self.back_buffer = None;
+        self.info = info;
+        self.scroll_region = None;
+        self.saved_cursor = None;
+        self.previous_line_end = None;
+        self.sprite_backup = SpriteBackup::new();
+        self.highlight = None;
+        self.reflow(cursor);
+        self.fill_screen(self.bg_color);
+        self.repaint();
+        if self.cursor_visible {
+            self.draw_cursor();
+        }
     }
 
-    /// Erases all text on the screen. Resets self.x_pos and self.y_pos.
-    pub fn clear(&mut self) {
-        self.x_pos = BORDER_PADDING;
-        self.y_pos = BORDER_PADDING;
-        self.framebuffer.fill(0);
+    /// Like [Self::new], but for a panel mounted turned by `orientation`. All
+    /// coordinates, and [Self::dimensions], are then of the turned screen.
+    pub fn with_orientation(framebuffer: &'a mut [u8], info: FrameBufferInfo, orientation: Orientation) -> Self {
+        Self::with_orientation_in(framebuffer, info, orientation, builtin_text())
     }
 
-    fn width(&self) -> usize {
-        self.info.width
+    /// Like [Self::with_orientation], but keeping the text in `text`, see
+    /// [Self::new_in].
+    pub fn with_orientation_in(
+        framebuffer: &'a mut [u8],
+        info: FrameBufferInfo,
+        orientation: Orientation,
+        text: &'a mut TextBuffer,
+    ) -> Self {
+        let mut logger = Self::new_in(framebuffer, info, text);
+        logger.orientation = orientation;
+        logger.clear();
+        logger
     }
 
-    fn height(&self) -> usize {
-        self.info.height
+    /// Like [Self::new], but only draws into the `width` x `height` rectangle
+    /// whose top left corner is at pixel (x, y) of the framebuffer, e.g. the
+    /// part of a larger mapping the caller owns. The writer treats that
+    /// rectangle as its whole screen: coordinates are relative to it and
+    /// drawing is clipped to it. The rectangle is clipped to the framebuffer.
+    ///
+    /// The writer borrows the framebuffer from the rectangle's first pixel
+    /// to its last, whole rows in between, but only ever touches the pixels
+    /// inside the rectangle. Writers for viewports stacked on top of each
+    /// other can share one framebuffer after splitting it with
+    /// `split_at_mut` at a row boundary.
+    ///
+    /// Viewports side by side aren't supported: they share framebuffer rows,
+    /// and a row can only be borrowed by one writer at a time, so the borrow
+    /// checker turns down a second writer over the same rows.
+    pub fn with_viewport(
+        framebuffer: &'a mut [u8],
+        info: FrameBufferInfo,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        Self::with_viewport_in(framebuffer, info, x, y, width, height, builtin_text())
     }
 
-    /// Writes a single char to the framebuffer. Takes care of special control characters,
-    /// such as newlines and carriage returns.
-    fn write_char(&mut self, c: char) {
-        match c {
-            '\n' => self.newline(),
-            'c' => {
-            // Change the color to blue
-            self.set_text_color([0, 0, 255]); // Example RGB for blue
+    /// Like [Self::with_viewport], but keeping the text in `text`, see
+    /// [Self::new_in].
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_viewport_in(
+        framebuffer: &'a mut [u8],
+        info: FrameBufferInfo,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        text: &'a mut TextBuffer,
+    ) -> Self {
+        let width = width.min(info.width.saturating_sub(x));
+        let height = height.min(info.height.saturating_sub(y));
+        let mut viewport = FrameBufferInfo { width, height, ..info };
+        let start = ((y * info.stride + x) * info.bytes_per_pixel).min(framebuffer.len());
+        let len = required_len(&viewport).min(framebuffer.len() - start);
+        viewport.byte_len = len;
+        Self::new_in(&mut framebuffer[start..start + len], viewport, text)
+    }
+
+    /// Copies the back buffer to the framebuffer in one pass.
+    /// Does nothing if the writer has no back buffer.
+    pub fn present(&mut self) {
+        if let Some(back_buffer) = &self.back_buffer {
+            let len = self.framebuffer.len().min(back_buffer.len());
+            self.framebuffer[..len].copy_from_slice(&back_buffer[..len]);
         }
-            '\t' => {
-        // Handle a tab by moving the x position forward
-        let tab_size = 4; // Define how many spaces a tab represents
-        let tab_width = font_constants::CHAR_RASTER_WIDTH * tab_size;
+    }
 
-        // Move the x position forward, making sure not to overflow the line
-        self.x_pos += tab_width;
+    /// Like [Self::present], but only copies the `w` x `h` rectangle whose top
+    /// left corner is at (x, y), clipped to the screen. The rest of the
+    /// framebuffer is left alone.
+    pub fn present_region(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        let x_end = x.saturating_add(w).min(self.width());
+        let y_end = y.saturating_add(h).min(self.height());
+        if x >= x_end || y >= y_end {
+            return;
+        }
+        // Rotation keeps a rectangle a rectangle, so mapping two opposite
+        // corners is enough.
+        let (px0, py0) = self.physical(x, y);
+        let (px1, py1) = self.physical(x_end - 1, y_end - 1);
+        let (x0, x1) = (px0.min(px1), px0.max(px1) + 1);
+        let (y0, y1) = (py0.min(py1), py0.max(py1) + 1);
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let stride = self.info.stride;
+        if let Some(back_buffer) = &self.back_buffer {
+            let len = self.framebuffer.len().min(back_buffer.len());
+            for y in y0..y1 {
+                let start = ((y * stride + x0) * bytes_per_pixel).min(len);
+                let end = ((y * stride + x1) * bytes_per_pixel).min(len);
+                self.framebuffer[start..end].copy_from_slice(&back_buffer[start..end]);
+            }
+        }
+    }
 
-        // If the x position goes beyond the screen width, move to the next line
-        if self.x_pos >= self.width() {
-            self.newline(); // Move to a new line
+    /// The buffer drawing operations write to: the back buffer if there is one,
+    /// the framebuffer otherwise.
+    fn buffer(&self) -> &[u8] {
+        match &self.back_buffer {
+            Some(back_buffer) => back_buffer,
+            None => self.framebuffer,
         }
     }
-            '\r' => self.carriage_return(),
-            c => {
-                let new_xpos = self.x_pos + font_constants::CHAR_RASTER_WIDTH;
-                if new_xpos >= self.width() {
-                    self.newline();
-                }
 
-                let new_ypos = self.y_pos + font_constants::CHAR_RASTER_HEIGHT.val() + BORDER_PADDING;
-                if new_ypos >= self.height() {
-                    self.clear();
-                }
+    /// Like [Self::buffer], but writable.
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        match &mut self.back_buffer {
+            Some(back_buffer) => back_buffer,
+            None => self.framebuffer,
+        }
+    }
 
-                self.write_rendered_char(get_char_raster(c));
+    /// The buffer the `len` bytes of a pixel at `byte_offset` of the screen
+    /// are read from, and where they are in it: the staging area while
+    /// [Self::write_line] stages them, [Self::buffer] otherwise.
+    fn pixel_source(&self, byte_offset: usize, len: usize) -> (&[u8], usize) {
+        match self.staged {
+            Some((start, end)) if byte_offset >= start && byte_offset + len <= end => {
+                (&self.line_staging[..], byte_offset - start)
             }
+            _ => (self.buffer(), byte_offset),
         }
     }
 
-    /// Prints a rendered char into the framebuffer.
-    /// Updates self.x_pos.
-    fn write_rendered_char(&mut self, rendered_char: RasterizedChar) {
-        for (y, row) in rendered_char.raster().iter().enumerate() {
-            for (x, intensity) in row.iter().enumerate() {
-                self.write_pixel(self.x_pos + x, self.y_pos + y, *intensity);
+    /// Like [Self::pixel_source], but writable.
+    fn pixel_target(&mut self, byte_offset: usize, len: usize) -> (&mut [u8], usize) {
+        match self.staged {
+            Some((start, end)) if byte_offset >= start && byte_offset + len <= end => {
+                (&mut self.line_staging[..], byte_offset - start)
             }
+            _ => (self.buffer_mut(), byte_offset),
         }
-        self.x_pos += rendered_char.width() + LETTER_SPACING;
     }
-    
 
+    pub fn set_text_color(&mut self, color: [u8; 3]) {
+        self.text_color = color;
+    }
 
-   
-    
-    fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
-        if intensity == 0 {
-            // Skip rendering for the background
+    /// Like [Self::set_text_color], with the color packed as `0xRRGGBB`.
+    pub fn set_text_color_u32(&mut self, color: u32) {
+        self.set_text_color(rgb(color));
+    }
+
+    pub fn set_bg_color(&mut self, color: [u8; 3]) {
+        self.bg_color = color;
+    }
+
+    /// Sets the colors [Self::reset_colors] and `ESC [ 0 m` go back to,
+    /// white on black to begin with. The current colors stay as they are.
+    pub fn set_default_colors(&mut self, fg: [u8; 3], bg: [u8; 3]) {
+        self.default_colors = (fg, bg);
+    }
+
+    /// Goes back to the text and background color set with
+    /// [Self::set_default_colors], e.g. after colored log output.
+    pub fn reset_colors(&mut self) {
+        (self.text_color, self.bg_color) = self.default_colors;
+    }
+
+    /// Sets the text color to black or white, whichever reads better on the
+    /// current background color.
+    pub fn set_contrasting_text_color(&mut self) {
+        self.text_color = if luminance(self.bg_color) < 128 { [255, 255, 255] } else { [0, 0, 0] };
+    }
+
+    /// When enabled (the default), the background of each glyph is painted
+    /// with the background color, so drawing over old text leaves no residue.
+    pub fn set_opaque_background(&mut self, opaque: bool) {
+        self.opaque_background = opaque;
+    }
+
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        let visible = self.cursor_visible;
+        self.erase_cursor();
+        self.cursor_shape = shape;
+        if visible {
+            self.draw_cursor();
+        }
+    }
+
+    /// Draws the cursor at the current write position in the text color.
+    pub fn draw_cursor(&mut self) {
+        let (width, height) = (self.char_width(), self.char_height());
+        let (x, y, w, h) = match self.cursor_shape {
+            CursorShape::Block => (self.cursor_x(), self.y_pos, width, height),
+            CursorShape::Underline => (
+                self.cursor_x(),
+                self.y_pos + height - CURSOR_THICKNESS,
+                width,
+                CURSOR_THICKNESS,
+            ),
+            CursorShape::Bar => (self.cursor_x(), self.y_pos, CURSOR_THICKNESS, height),
+        };
+        let h = self.clip_to_text_bottom(y, h);
+        self.draw_filled_rect(x, y, w, h, self.text_color);
+        self.cursor_visible = true;
+    }
+
+    /// Paints the cursor cell at the current write position with the
+    /// background color, or draws the char in it again if there is one, e.g.
+    /// the last char of a full line while the wrap is pending.
+    pub fn erase_cursor(&mut self) {
+        let (width, height) = (self.char_width(), self.clip_to_text_bottom(self.y_pos, self.char_height()));
+        self.draw_filled_rect(self.cursor_x(), self.y_pos, width, height, self.bg_color);
+        let (column, row) = self.cursor_position_cell();
+        if column < self.screen_columns() && row < self.screen_rows() && self.cells[row][column] != Cell::BLANK {
+            self.draw_cell(column, row, false);
+        }
+        self.cursor_visible = false;
+    }
+
+    /// Draws or erases the cursor, whichever it isn't currently.
+    /// Meant to be called periodically, e.g. from a timer interrupt.
+    pub fn toggle_cursor(&mut self) {
+        if self.cursor_visible {
+            self.erase_cursor();
+        } else {
+            self.draw_cursor();
+        }
+    }
+
+    /// Remembers the current write position, like VT100 `ESC [ s`.
+    pub fn save_cursor(&mut self) {
+        self.saved_cursor = Some((self.x_pos, self.y_pos));
+    }
+
+    /// Returns to the position stored by [Self::save_cursor], like VT100
+    /// `ESC [ u`. Does nothing if no position was saved.
+    pub fn restore_cursor(&mut self) {
+        if let Some((x, y)) = self.saved_cursor {
+            self.wrap_pending = false;
+            self.x_pos = x;
+            self.y_pos = y;
+        }
+    }
+
+    /// Moves the write position to cell (col, row), counted from 0 at the top
+    /// left of the text area. Positions past the last column or row go to
+    /// the last one.
+    pub fn move_to(&mut self, col: usize, row: usize) {
+        self.flush_word();
+        let col = col.min(self.screen_columns().saturating_sub(1));
+        let row = row.min(self.screen_rows().saturating_sub(1));
+        let (x, y) = self.cell_origin(col, row);
+        self.wrap_pending = false;
+        self.x_pos = match self.direction {
+            TextDirection::Ltr => x,
+            TextDirection::Rtl => x + self.char_width(),
+        };
+        self.y_pos = y;
+    }
+
+    /// Moves the write position up `n` rows, stopping at the top one.
+    pub fn cursor_up(&mut self, n: usize) {
+        let (col, row) = self.cursor_position_cell();
+        self.move_to(col, row.saturating_sub(n));
+    }
+
+    /// Moves the write position down `n` rows, stopping at the bottom one.
+    pub fn cursor_down(&mut self, n: usize) {
+        let (col, row) = self.cursor_position_cell();
+        self.move_to(col, row.saturating_add(n));
+    }
+
+    /// Moves the write position right `n` cells, stopping at the last one.
+    pub fn cursor_forward(&mut self, n: usize) {
+        let (col, row) = self.cursor_position_cell();
+        self.move_to(col.saturating_add(n), row);
+    }
+
+    /// Moves the write position left `n` cells, stopping at the first one.
+    pub fn cursor_back(&mut self, n: usize) {
+        let (col, row) = self.cursor_position_cell();
+        self.move_to(col.saturating_sub(n), row);
+    }
+
+    /// The (column, row) of the cell the next char goes in, in either text
+    /// direction.
+    fn cursor_position_cell(&self) -> (usize, usize) {
+        let cell_width = self.cell_width();
+        let (_, row) = self.cursor_cell();
+        (self.cursor_x().saturating_sub(self.text_left()) / cell_width, row)
+    }
+
+    /// Sets the gap in pixels between two text lines.
+    pub fn set_line_spacing(&mut self, spacing: usize) {
+        self.line_spacing = spacing;
+    }
+
+    /// Sets the gap in pixels between two chars on a line.
+    pub fn set_letter_spacing(&mut self, spacing: usize) {
+        self.letter_spacing = spacing;
+    }
+
+    /// With `Some(advance)`, moves on exactly `advance` pixels after every
+    /// char, centering narrower glyphs in the cell, so columns line up in
+    /// tables whatever the glyph widths. Letter spacing is then ignored.
+    /// `None` goes back to advancing by each glyph's width.
+    pub fn set_monospace_advance(&mut self, advance: Option<usize>) {
+        self.fixed_advance = advance.filter(|&advance| advance > 0);
+    }
+
+    /// Sets the margin in pixels around the text. Takes effect from the next
+    /// line on, or right away after a [Self::clear].
+    pub fn set_border_padding(&mut self, padding: usize) {
+        self.border_padding = padding;
+    }
+
+    /// Draws text at an integer multiple of the font size, e.g. 2 for twice
+    /// as wide and high. A scale of 0 is treated as 1.
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.max(1);
+    }
+
+    /// Draws the text written from now on double width and/or height. Lines
+    /// get taller in the double height modes.
+    pub fn set_size_mode(&mut self, mode: SizeMode) {
+        self.size_mode = mode;
+    }
+
+    /// Draws a shadow or outline behind the text written from now on.
+    pub fn set_text_effect(&mut self, effect: TextEffect) {
+        self.text_effect = effect;
+    }
+
+    /// Turns on the given decorations for the text written from now on.
+    pub fn set_attr(&mut self, attrs: TextAttrs) {
+        self.text_attrs.insert(attrs);
+    }
+
+    /// Turns off the given decorations for the text written from now on.
+    pub fn clear_attr(&mut self, attrs: TextAttrs) {
+        self.text_attrs.remove(attrs);
+    }
+
+    /// Draws text with the text and background colors swapped.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    /// Sets the distance between tab stops, in chars.
+    pub fn set_tab_width(&mut self, chars: usize) {
+        self.tab_size = chars;
+    }
+
+    pub fn set_wrap_mode(&mut self, mode: WrapMode) {
+        self.flush_word();
+        self.wrap_mode = mode;
+    }
+
+    /// Sets which way the text runs. Takes effect from the start of a line:
+    /// the cursor moves to where lines start in the new direction.
+    pub fn set_text_direction(&mut self, direction: TextDirection) {
+        self.flush_word();
+        self.direction = direction;
+        self.carriage_return();
+    }
+
+    /// Turns line wrapping on or off. With it off, the chars that don't fit
+    /// on a line are dropped until the next newline, e.g. for a status line
+    /// that should only ever take up one row. On by default.
+    pub fn set_autowrap(&mut self, autowrap: bool) {
+        self.flush_word();
+        self.autowrap = autowrap;
+    }
+
+    /// Writes a char, holding back the chars of a word in [WrapMode::Word]
+    /// until it's known whether the whole word fits on the line.
+    fn put_char(&mut self, c: char) {
+        if is_combining_mark(c) {
             return;
         }
-    
-        let pixel_offset = y * self.info.stride + x;
-        let color = match self.info.pixel_format {
-            PixelFormat::Rgb => [self.text_color[0], self.text_color[1], self.text_color[2], 0],
-            PixelFormat::Bgr => [self.text_color[2], self.text_color[1], self.text_color[0], 0],
-            PixelFormat::U8 => [if intensity > 200 { 0xf } else { 0 }, 0, 0, 0],
-            other => {
-                self.info.pixel_format = PixelFormat::Rgb;
-                panic!("Pixel format {:?} not supported in logger", other);
+        if self.wrap_mode == WrapMode::Word && !c.is_whitespace() && !c.is_control() {
+            if self.word_len == WORD_BUFFER_LEN {
+                self.flush_word();
             }
+            self.word[self.word_len] = c;
+            self.word_len += 1;
+            return;
+        }
+        self.flush_word();
+        self.write_char(c);
+    }
+
+    /// Draws the held back word, starting a new line first if the word
+    /// doesn't fit on the current one but would fit on an empty one.
+    fn flush_word(&mut self) {
+        if self.word_len == 0 {
+            return;
+        }
+        let word_width = self.word_len * self.cell_width();
+        let fits = |x: usize| match self.direction {
+            TextDirection::Ltr => x + word_width <= self.text_right(),
+            TextDirection::Rtl => x >= self.text_left() + word_width,
         };
-        let bytes_per_pixel = self.info.bytes_per_pixel;
-        let byte_offset = pixel_offset * bytes_per_pixel;
-        self.framebuffer[byte_offset..(byte_offset + bytes_per_pixel)]
-            .copy_from_slice(&color[..bytes_per_pixel]);
-        let _ = unsafe { ptr::read_volatile(&self.framebuffer[byte_offset]);
-        };
+        if self.autowrap && !fits(self.x_pos) && fits(self.line_start()) {
+            self.newline();
+        }
+        for i in 0..self.word_len {
+            self.write_char(self.word[i]);
+        }
+        self.word_len = 0;
+    }
+
+    fn newline(&mut self) {
+        self.finish_row();
+        self.previous_line_end = Some(self.x_pos);
+        self.y_pos += self.line_height();
+        // The line after the last row is as far down as the position goes;
+        // further newlines scroll right away instead of leaving it off screen
+        // for the next char to scroll back by only one line.
+        let rows = self.rows();
+        let limit = self.text_top() + rows * self.line_height();
+        if self.scroll_region.is_none() && rows > 0 && self.y_pos > limit {
+            self.scroll_up(1);
+            self.y_pos = self.y_pos.min(limit);
+        }
+        self.carriage_return();
     }
-    
-}
 
-unsafe impl Send for FrameBufferWriter {}
-unsafe impl Sync for FrameBufferWriter {}
+    fn carriage_return(&mut self) {
+        self.wrap_pending = false;
+        self.x_pos = self.line_start();
+    }
 
-impl Write for FrameBufferWriter {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            self.write_char(c);
+    /// The self.x_pos lines start at: the left edge of the text area, or its
+    /// right edge in [TextDirection::Rtl].
+    fn line_start(&self) -> usize {
+        match self.direction {
+            TextDirection::Ltr => self.text_left(),
+            TextDirection::Rtl => self.text_right().saturating_sub(self.border_padding),
         }
-        Ok(())
+    }
+
+    /// The left edge of the cell the next char goes in.
+    fn cursor_x(&self) -> usize {
+        match self.direction {
+            TextDirection::Ltr => self.x_pos,
+            TextDirection::Rtl => self.x_pos.saturating_sub(self.char_width()),
+        }
+    }
+
+    /// Vertical distance in pixels between the tops of two text lines.
+    fn line_height(&self) -> usize {
+        self.char_height() + self.line_spacing
+    }
+
+    /// Width in pixels of a char as drawn, i.e. including the scale and size mode.
+    fn char_width(&self) -> usize {
+        let width = self.font.map_or(font_constants::CHAR_RASTER_WIDTH, |font| font.width());
+        width * self.x_scale()
+    }
+
+    /// Pixels from the left edge of one char cell to the next.
+    fn cell_width(&self) -> usize {
+        self.fixed_advance.unwrap_or(self.char_width() + self.letter_spacing)
+    }
+
+    /// Height in pixels of a char as drawn, i.e. including the scale and size mode.
+    fn char_height(&self) -> usize {
+        let height = self.font.map_or(CHAR_RASTER_HEIGHT.val(), |font| font.height());
+        height * self.y_scale()
+    }
+
+    /// How many pixels wide each raster pixel is drawn.
+    fn x_scale(&self) -> usize {
+        self.scale * self.size_mode.factors().0
+    }
+
+    /// How many pixels high each raster pixel is drawn.
+    fn y_scale(&self) -> usize {
+        self.scale * self.size_mode.factors().1
+    }
+
+    /// Writes a string starting at the given pixel position, leaving the
+    /// streaming cursor where it was. Newlines return to the starting x.
+    /// Labels never wrap or scroll the screen; whatever falls off the edge of
+    /// the framebuffer is clipped.
+    pub fn write_str_at(&mut self, x: usize, y: usize, s: &str) {
+        let (saved_x, saved_y) = (self.x_pos, self.y_pos);
+        self.y_pos = y;
+        for line in s.split('\n') {
+            if self.y_pos >= self.height() {
+                break;
+            }
+            self.x_pos = x;
+            for c in line.chars() {
+                if self.x_pos >= self.width() {
+                    break;
+                }
+                if !c.is_control() && !is_combining_mark(c) {
+                    self.draw_char(c);
+                }
+            }
+            self.y_pos += self.line_height();
+        }
+        self.x_pos = saved_x;
+        self.y_pos = saved_y;
+    }
+
+    /// Writes `s` in `color`, then goes back to the previous text color.
+    pub fn write_colored(&mut self, color: [u8; 3], s: &str) {
+        let previous_color = core::mem::replace(&mut self.text_color, color);
+        let _ = self.write_str(s);
+        self.text_color = previous_color;
+    }
+
+    /// Like [Self::write_colored], for formatted text.
+    pub fn write_colored_fmt(&mut self, color: [u8; 3], args: fmt::Arguments) {
+        let previous_color = core::mem::replace(&mut self.text_color, color);
+        let _ = self.write_fmt(args);
+        self.text_color = previous_color;
+    }
+
+    /// Writes raw bytes without going through `str` and its UTF-8 checks.
+    /// Printable ASCII and control bytes are handled like in `write_str`,
+    /// other bytes show up as [font_constants::BACKUP_CHAR].
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_chars(bytes.iter().map(|&byte| match byte {
+            0x00..=0x7e => byte as char,
+            _ => BACKUP_CHAR,
+        }));
+    }
+
+    /// Writes `n` in decimal, like `write!(writer, "{}", n)` but without going
+    /// through `core::fmt`.
+    pub fn write_u64(&mut self, n: u64) {
+        self.write_u64_padded(n, 0);
+    }
+
+    /// Writes `n` in decimal, padded with spaces on the left to at least
+    /// `width` chars, like `"{:>width$}"`.
+    pub fn write_u64_padded(&mut self, n: u64, width: usize) {
+        let mut buffer = [0; 20];
+        let digits = format_digits(n, 10, &mut buffer);
+        for _ in digits.len()..width {
+            self.write_bytes(b" ");
+        }
+        self.write_bytes(digits);
+    }
+
+    /// Writes `n` in lowercase hex without a prefix, like `"{:x}"`.
+    pub fn write_hex(&mut self, n: u64) {
+        let mut buffer = [0; 20];
+        let digits = format_digits(n, 16, &mut buffer);
+        self.write_bytes(digits);
+    }
+
+    /// Feeds chars through the escape parser and draws or handles them, the
+    /// way written text is.
+    fn write_chars(&mut self, chars: impl Iterator<Item = char>) {
+        self.scroll_to_live();
+        // Don't leave a smear where the next glyph goes; the cursor is drawn
+        // again at the new position afterwards.
+        let cursor_visible = self.cursor_visible;
+        if cursor_visible {
+            self.erase_cursor();
+        }
+        for c in chars {
+            match self.pending_escape.feed(c) {
+                Parsed::Char(c) => self.put_char(c),
+                Parsed::Pending => {}
+                Parsed::Csi(sequence) => {
+                    // The held back word was written with the old colors.
+                    self.flush_word();
+                    self.handle_csi(&sequence);
+                }
+            }
+        }
+        // A word split across two calls may still get broken, but nothing
+        // written is left off the screen.
+        self.flush_word();
+        if cursor_visible {
+            self.draw_cursor();
+        }
+    }
+
+    /// Writes `c` like [Write::write_char], but instead of drawing the
+    /// backup char fails with [WriterError::MissingGlyph] if the font has no
+    /// glyph for it, and with [WriterError::OutOfBounds] instead of dropping
+    /// it if autowrap is off and the line is full. Nothing is drawn then.
+    pub fn try_write_char(&mut self, c: char) -> Result<(), WriterError> {
+        if !c.is_control() && !is_combining_mark(c) && !self.pending_escape.is_pending() {
+            if !self.has_glyph(c) {
+                return Err(WriterError::MissingGlyph(c));
+            }
+            if !self.autowrap && !self.fits_on_line(self.char_width()) {
+                return Err(WriterError::OutOfBounds);
+            }
+        }
+        self.write_chars(core::iter::once(c));
+        Ok(())
+    }
+
+    /// Erases all text on the screen, filling it with the background color.
+    /// Resets self.x_pos and self.y_pos.
+    pub fn clear(&mut self) {
+        self.clear_color(self.bg_color);
+    }
+
+    /// Erases all text on the screen, filling it with `color`. Resets
+    /// self.x_pos and self.y_pos. With a text area set, only the area is
+    /// filled.
+    pub fn clear_color(&mut self, color: [u8; 3]) {
+        self.wrap_pending = false;
+        self.x_pos = self.line_start();
+        self.y_pos = self.text_top();
+        if self.text_area.is_some() {
+            let (x, y, w, h) = self.text_area();
+            self.draw_filled_rect(x, y, w, h, color);
+        } else {
+            self.fill_screen(color);
+        }
+        self.cells.fill([Cell::BLANK; MAX_COLUMNS]);
+        self.highlight = None;
+    }
+
+    /// Scales the color channels of every pixel drawn from now on by
+    /// `factor / 255`. 255, the default, draws colors unchanged.
+    pub fn set_brightness(&mut self, factor: u8) {
+        self.brightness = factor;
+    }
+
+    /// Scales the color channels of everything already on screen by
+    /// `factor / 255`, e.g. to dim the screen behind a modal. Unlike
+    /// [Self::set_brightness] this doesn't affect later drawing.
+    pub fn dim_screen(&mut self, factor: u8) {
+        if factor == 255 {
+            return;
+        }
+        let (width, height) = (self.info.width, self.info.height);
+        let (stride, bytes_per_pixel) = (self.info.stride, self.info.bytes_per_pixel);
+        let format = self.info.pixel_format;
+        self.mark_all_dirty();
+        let buffer = self.buffer_mut();
+        for y in 0..height {
+            for x in 0..width {
+                let offset = (y * stride + x) * bytes_per_pixel;
+                let Some(pixel) = buffer.get_mut(offset..offset + bytes_per_pixel) else {
+                    return;
+                };
+                dim_pixel(pixel, format, factor);
+            }
+        }
+    }
+
+    /// Shifts the screen contents up by `lines` text lines and blanks the rows
+    /// exposed at the bottom. Moves self.y_pos up by the same distance.
+    ///
+    /// With a scroll region set, only the lines inside it move, and only a
+    /// cursor in or right below the region moves with them. With a text
+    /// area set, only the area moves.
+    pub fn scroll_up(&mut self, lines: usize) {
+        let region = self.scroll_region.or_else(|| self.text_area.map(|_| (0, self.screen_rows())));
+        if let Some((top, bottom)) = region {
+            for _ in 0..lines.min(bottom - top) {
+                self.shift_lines(top, bottom, false);
+            }
+            let (_, row) = self.cursor_cell();
+            if (top..=bottom).contains(&row) {
+                let top_y = self.text_top() + top * self.line_height();
+                self.y_pos = self.y_pos.saturating_sub(lines * self.line_height()).max(top_y);
+            }
+            return;
+        }
+
+        let pixel_rows = lines * self.line_height();
+        if pixel_rows >= self.height() {
+            self.clear();
+            return;
+        }
+
+        self.scroll_pixels_up(pixel_rows);
+        self.y_pos = self.y_pos.saturating_sub(pixel_rows).max(self.border_padding);
+        self.scroll_cells(lines);
+    }
+
+    /// Restricts scrolling to the text rows `top_row` to `bottom_row`, both
+    /// included, like DECSTBM. Rows above and below stay where they are, e.g.
+    /// for a fixed header or status line. Invalid regions are ignored.
+    pub fn set_scroll_region(&mut self, top_row: usize, bottom_row: usize) {
+        if top_row < bottom_row && bottom_row < self.screen_rows() {
+            self.scroll_region = Some((top_row, bottom_row + 1));
+        }
+    }
+
+    /// Lets the whole screen scroll again.
+    pub fn reset_scroll_region(&mut self) {
+        self.scroll_region = None;
+    }
+
+    /// The text rows `top..bottom` that scroll.
+    fn scrolling_rows(&self) -> (usize, usize) {
+        self.scroll_region.unwrap_or((0, self.screen_rows()))
+    }
+
+    /// Opens a blank text line at `row`: the lines from there to the bottom of
+    /// the scroll region move down by one and the last one is dropped. Does
+    /// nothing outside the scroll region.
+    pub fn insert_line(&mut self, row: usize) {
+        let (top, bottom) = self.scrolling_rows();
+        if (top..bottom).contains(&row) {
+            self.shift_lines(row, bottom, true);
+        }
+    }
+
+    /// Removes the text line at `row`: the lines below it in the scroll
+    /// region move up by one and the bottom one is left blank. Does nothing
+    /// outside the scroll region.
+    pub fn delete_line(&mut self, row: usize) {
+        let (top, bottom) = self.scrolling_rows();
+        if (top..bottom).contains(&row) {
+            self.shift_lines(row, bottom, false);
+        }
+    }
+
+    /// Moves the text lines from `top` up to, but excluding, `bottom` one
+    /// line down or up, pixels and cells alike, and blanks the line that
+    /// opens up at the top or bottom.
+    fn shift_lines(&mut self, top: usize, bottom: usize, down: bool) {
+        let line_height = self.line_height();
+        let start = (self.text_top() + top * line_height).min(self.text_bottom());
+        let end = (self.text_top() + bottom * line_height).min(self.text_bottom()).max(start);
+        let moved = (end - start).saturating_sub(line_height);
+        // Only the text area's own columns move, so nothing left or right of
+        // it (or of a viewport) is touched.
+        let (x, _, width, _) = self.text_area();
+        if down {
+            self.copy_rect((x, start), (x, start + line_height), width, moved);
+            self.draw_filled_rect(x, start, width, end - start - moved, self.bg_color);
+        } else {
+            self.copy_rect((x, end - moved), (x, start), width, moved);
+            self.draw_filled_rect(x, start + moved, width, end - start - moved, self.bg_color);
+        }
+        self.shift_cells(top, bottom, down);
+    }
+
+    /// Moves the cell rows from `top` up to, but excluding, `bottom` one row
+    /// down or up and blanks the row that opens up.
+    fn shift_cells(&mut self, top: usize, bottom: usize, down: bool) {
+        let bottom = bottom.min(MAX_ROWS);
+        if top < bottom {
+            let cells = &mut self.cells[top..bottom];
+            if down {
+                cells.copy_within(..cells.len() - 1, 1);
+                cells[0] = [Cell::BLANK; MAX_COLUMNS];
+            } else {
+                cells.copy_within(1.., 0);
+                cells[cells.len() - 1] = [Cell::BLANK; MAX_COLUMNS];
+            }
+        }
+    }
+
+    /// Shifts the framebuffer contents up by `n` pixel rows and blanks the
+    /// rows exposed at the bottom. Unlike [Self::scroll_up] this leaves the
+    /// cursor and the text cells alone, so it suits smooth scrolling effects.
+    pub fn scroll_pixels_up(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(height);
+        self.copy_rect((0, n), (0, 0), width, height - n);
+        self.draw_filled_rect(0, height - n, width, n, self.bg_color);
+    }
+
+    /// Shifts every pixel row left by `n` pixels and blanks the columns
+    /// exposed at the right edge. Each row is shifted on its own, so nothing
+    /// moves into the row above. Leaves the cursor and the text cells alone.
+    pub fn scroll_pixels_left(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(width);
+        self.copy_rect((n, 0), (0, 0), width - n, height);
+        self.draw_filled_rect(width - n, 0, n, height, self.bg_color);
+    }
+
+    /// Returns the (width, height) of the display in pixels. Dividing by the
+    /// glyph size gives a rough char count; [Self::columns] and [Self::rows]
+    /// also take the border padding and spacing into account.
+    ///
+    /// ```
+    /// use kernel_with_bootloader::writer::constants::font_constants::CHAR_RASTER_WIDTH;
+    /// use kernel_with_bootloader::writer::FrameBufferWriter;
+    ///
+    /// fn columns(writer: &FrameBufferWriter) -> usize {
+    ///     let (width, _) = writer.dimensions();
+    ///     width / CHAR_RASTER_WIDTH
+    /// }
+    /// ```
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width(), self.height())
+    }
+
+    /// How many chars fit on a text line, after border padding and letter
+    /// spacing. The last char needs no spacing after it.
+    pub fn columns(&self) -> usize {
+        let available = self.text_right().saturating_sub(self.text_left());
+        match available.checked_sub(self.char_width()) {
+            Some(rest) => rest / self.cell_width() + 1,
+            None => 0,
+        }
+    }
+
+    /// How many text lines fit on the screen before it scrolls, after border
+    /// padding and line spacing. The last line needs no spacing after it.
+    pub fn rows(&self) -> usize {
+        let bottom = self.text_bottom().saturating_sub(self.border_padding + self.char_height());
+        bottom.saturating_sub(self.text_top()).div_ceil(self.line_height())
+    }
+
+    /// Returns the (x, y) pixel position where the next char will be drawn.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.x_pos, self.y_pos)
+    }
+
+    /// Returns the layout and pixel format of the framebuffer.
+    pub fn info(&self) -> FrameBufferInfo {
+        self.info
+    }
+
+    /// Pixels from the start of one framebuffer row to the start of the next.
+    pub fn stride(&self) -> usize {
+        self.info.stride
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.info.bytes_per_pixel
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.info.pixel_format
+    }
+
+    /// Replaces the stride reported by the firmware, for the ones that get it
+    /// wrong, and redraws the text with it. Returns false and changes nothing
+    /// if `stride` is less than the width or the framebuffer (or back buffer)
+    /// is too short for it.
+    pub fn override_stride(&mut self, stride: usize) -> bool {
+        let info = FrameBufferInfo { stride, ..self.info };
+        let fits = |buffer: &[u8]| buffer.len() >= required_len(&info);
+        if stride < info.width || !fits(self.framebuffer) || !self.back_buffer.as_deref().is_none_or(fits) {
+            return false;
+        }
+        self.info = info;
+        self.fill_screen(self.bg_color);
+        self.repaint();
+        if self.cursor_visible {
+            self.draw_cursor();
+        }
+        true
+    }
+
+    /// Returns a stable 64-bit FNV-1a hash of the visible framebuffer bytes.
+    /// Rows are hashed one by one, so padding between them doesn't count.
+    pub fn checksum(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        let row_bytes = self.info.width * self.info.bytes_per_pixel;
+        let stride_bytes = self.info.stride * self.info.bytes_per_pixel;
+        let mut hash = FNV_OFFSET_BASIS;
+        for y in 0..self.info.height {
+            let start = (y * stride_bytes).min(self.framebuffer.len());
+            let end = (start + row_bytes).min(self.framebuffer.len());
+            for &byte in &self.framebuffer[start..end] {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        hash
+    }
+
+    /// Copies the raw framebuffer bytes, padding included, into `out`.
+    /// Copies only as much as fits if `out` is shorter than the framebuffer.
+    pub fn snapshot(&self, out: &mut [u8]) {
+        let len = out.len().min(self.framebuffer.len());
+        out[..len].copy_from_slice(&self.framebuffer[..len]);
+    }
+
+    /// Returns the (width, height) in pixels that `s` would take up when
+    /// written, without drawing anything. Doesn't account for wrapping.
+    pub fn measure_str(&self, s: &str) -> (usize, usize) {
+        let stop_width = self.char_width() * self.tab_size.max(1);
+        let (mut max_width, mut line_width, mut lines) = (0, 0, 1);
+        for c in s.chars() {
+            match c {
+                '\n' => {
+                    max_width = max_width.max(line_width);
+                    line_width = 0;
+                    lines += 1;
+                }
+                '\r' => {
+                    max_width = max_width.max(line_width);
+                    line_width = 0;
+                }
+                '\t' => line_width = (line_width / stop_width + 1) * stop_width,
+                _ => line_width += self.cell_width(),
+            }
+        }
+        max_width = max_width.max(line_width);
+        (max_width, (lines - 1) * self.line_height() + self.char_height())
+    }
+
+    /// Writes `s` horizontally centered at pixel row `y`, leaving the
+    /// streaming cursor where it was. Text wider than the screen starts at
+    /// the left border.
+    pub fn write_centered(&mut self, y: usize, s: &str) {
+        let (width, _) = self.measure_str(s);
+        let (area_x, _, area_width, _) = self.text_area();
+        let x = (area_x + area_width.saturating_sub(width) / 2).max(self.text_left());
+        self.write_str_at(x, y, s);
+    }
+
+    /// Writes `s` so that it ends at the right border of pixel row `y`,
+    /// leaving the streaming cursor where it was. Text wider than the screen
+    /// starts at the left border.
+    pub fn write_right_aligned(&mut self, y: usize, s: &str) {
+        let (width, _) = self.measure_str(s);
+        let x = self
+            .text_right()
+            .saturating_sub(self.border_padding + width)
+            .max(self.text_left());
+        self.write_str_at(x, y, s);
+    }
+
+    /// Draws a frame of box-drawing chars `cols` cells wide and `rows` cells
+    /// high, border included, with its top left corner in cell (x, y). Falls
+    /// back to `+`, `-` and `|` for chars the font doesn't have. Leaves the
+    /// streaming cursor where it was; boxes smaller than 2x2 aren't drawn,
+    /// and the parts of a box that are off the screen are skipped.
+    pub fn draw_box(&mut self, x: usize, y: usize, cols: usize, rows: usize) {
+        if cols < 2 || rows < 2 {
+            return;
+        }
+        let glyph = |c: char, fallback: char| {
+            if self.has_glyph(c) { c } else { fallback }
+        };
+        let [horizontal, vertical, top_left, top_right, bottom_left, bottom_right] = [
+            glyph('─', '-'),
+            glyph('│', '|'),
+            glyph('┌', '+'),
+            glyph('┐', '+'),
+            glyph('└', '+'),
+            glyph('┘', '+'),
+        ];
+        let (right, bottom) = (x.saturating_add(cols - 1), y.saturating_add(rows - 1));
+        let (screen_columns, screen_rows) = (self.columns(), self.rows());
+        let (saved_x, saved_y) = (self.x_pos, self.y_pos);
+        let put = |writer: &mut Self, column: usize, row: usize, c: char| {
+            if column >= screen_columns || row >= screen_rows {
+                return;
+            }
+            (writer.x_pos, writer.y_pos) = writer.cell_origin(column, row);
+            writer.record_cell(c);
+            writer.draw_char(c);
+        };
+        put(self, x, y, top_left);
+        put(self, right, y, top_right);
+        put(self, x, bottom, bottom_left);
+        put(self, right, bottom, bottom_right);
+        for column in x.saturating_add(1)..right.min(screen_columns) {
+            put(self, column, y, horizontal);
+            put(self, column, bottom, horizontal);
+        }
+        for row in y.saturating_add(1)..bottom.min(screen_rows) {
+            put(self, x, row, vertical);
+            put(self, right, row, vertical);
+        }
+        self.x_pos = saved_x;
+        self.y_pos = saved_y;
+    }
+
+    /// Writes a classic hex dump of `data`, 16 bytes per line: the address
+    /// (counting from `base`), the bytes in hex, then the printable ones as
+    /// ASCII with `.` standing in for the rest. Addresses past `usize::MAX`
+    /// wrap around to 0.
+    pub fn hexdump(&mut self, base: usize, data: &[u8]) {
+        for (line, chunk) in data.chunks(16).enumerate() {
+            let _ = write!(self, "{:08x}: ", base.wrapping_add(line * 16));
+            for byte in chunk {
+                let _ = write!(self, "{:02x} ", byte);
+            }
+            // pad a short last line so its ASCII column lines up
+            for _ in chunk.len()..16 {
+                let _ = self.write_str("   ");
+            }
+            let _ = self.write_str(" ");
+            for &byte in chunk {
+                let c = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+                let _ = write!(self, "{}", c);
+            }
+            let _ = self.write_str("\n");
+        }
+    }
+
+    /// Replaces the screen contents with a white on blue panic report showing
+    /// the panic message and where it happened. Doesn't allocate.
+    pub fn render_panic(&mut self, info: &core::panic::PanicInfo) {
+        self.render_panic_report(info.message(), info.location());
+    }
+
+    /// The report [Self::render_panic] shows, for any message and location.
+    /// A PanicInfo can't be made outside a real panic.
+    fn render_panic_report(&mut self, message: impl fmt::Display, location: Option<&core::panic::Location>) {
+        // Whatever was in progress when we panicked doesn't matter anymore.
+        self.pending_escape = EscapeParser::new();
+        self.word_len = 0;
+        self.cursor_visible = false;
+        self.text_attrs = TextAttrs::empty();
+        self.reverse = false;
+        self.opaque_background = true;
+
+        self.text_area = None;
+
+        self.set_bg_color(PANIC_BG_COLOR);
+        self.set_text_color(DEFAULT_TEXT_COLOR);
+        self.draw_filled_rect(0, 0, self.width(), self.height(), self.bg_color);
+        self.cells.fill([Cell::BLANK; MAX_COLUMNS]);
+        self.wrap_pending = false;
+        self.x_pos = self.border_padding;
+        self.y_pos = self.border_padding;
+
+        let _ = writeln!(self, "KERNEL PANIC");
+        let _ = writeln!(self, "{}", message);
+        if let Some(location) = location {
+            let _ = writeln!(self, "at {}:{}:{}", location.file(), location.line(), location.column());
+        }
+        self.present();
+    }
+
+    /// Blanks the current text row from the cursor to the right edge, like
+    /// VT100 `ESC [ K`.
+    pub fn clear_to_end_of_line(&mut self) {
+        let width = self.text_right().saturating_sub(self.x_pos);
+        let height = self.clip_to_text_bottom(self.y_pos, self.char_height());
+        self.draw_filled_rect(self.x_pos, self.y_pos, width, height, self.bg_color);
+        let (column, row) = self.cursor_cell();
+        self.blank_cells(row, column, MAX_COLUMNS);
+    }
+
+    /// Blanks the current text row from the left edge up to and including the
+    /// cursor cell, like VT100 `ESC [ 1 K`.
+    pub fn clear_to_start_of_line(&mut self) {
+        let (x, _, _, _) = self.text_area();
+        let width = (self.x_pos + self.char_width()).saturating_sub(x);
+        let height = self.clip_to_text_bottom(self.y_pos, self.char_height());
+        self.draw_filled_rect(x, self.y_pos, width, height, self.bg_color);
+        let (column, row) = self.cursor_cell();
+        self.blank_cells(row, 0, column + 1);
+    }
+
+    /// Blanks the whole current text row, like VT100 `ESC [ 2 K`.
+    pub fn clear_line(&mut self) {
+        let (x, _, width, _) = self.text_area();
+        let height = self.clip_to_text_bottom(self.y_pos, self.char_height());
+        self.draw_filled_rect(x, self.y_pos, width, height, self.bg_color);
+        let (_, row) = self.cursor_cell();
+        self.blank_cells(row, 0, MAX_COLUMNS);
+    }
+
+    /// Blanks everything from the cursor to the end of the screen, like
+    /// VT100 `ESC [ J`.
+    pub fn clear_to_end_of_screen(&mut self) {
+        self.clear_to_end_of_line();
+        let below = self.y_pos + self.char_height();
+        let (x, _, width, _) = self.text_area();
+        let height = self.text_bottom().saturating_sub(below);
+        self.draw_filled_rect(x, below, width, height, self.bg_color);
+        let (_, row) = self.cursor_cell();
+        for row in row + 1..MAX_ROWS {
+            self.blank_cells(row, 0, MAX_COLUMNS);
+        }
+    }
+
+    /// Blanks everything from the start of the screen up to and including the
+    /// cursor cell, like VT100 `ESC [ 1 J`.
+    pub fn clear_from_start_of_screen(&mut self) {
+        let (x, y, width, _) = self.text_area();
+        self.draw_filled_rect(x, y, width, self.y_pos.saturating_sub(y), self.bg_color);
+        self.clear_to_start_of_line();
+        let (_, row) = self.cursor_cell();
+        for row in 0..row {
+            self.blank_cells(row, 0, MAX_COLUMNS);
+        }
+    }
+
+    /// Blanks the whole text area without moving the cursor, like VT100
+    /// `ESC [ 2 J`. Unlike [Self::clear], the next char goes where the
+    /// cursor was.
+    pub fn clear_screen(&mut self) {
+        let (x, y, width, height) = self.text_area();
+        self.draw_filled_rect(x, y, width, height, self.bg_color);
+        self.cells.fill([Cell::BLANK; MAX_COLUMNS]);
+        self.highlight = None;
+    }
+
+    /// Width of the screen as seen by the user, i.e. after rotation.
+    fn width(&self) -> usize {
+        if self.is_sideways() { self.info.height } else { self.info.width }
+    }
+
+    /// Height of the screen as seen by the user, i.e. after rotation.
+    fn height(&self) -> usize {
+        if self.is_sideways() { self.info.width } else { self.info.height }
+    }
+
+    /// Whether a glyph `width` pixels wide still fits on the current line.
+    fn fits_on_line(&self, width: usize) -> bool {
+        match self.direction {
+            TextDirection::Ltr => self.x_pos + width <= self.text_right(),
+            TextDirection::Rtl => self.x_pos >= self.text_left() + width,
+        }
+    }
+
+    /// Writes a single char to the framebuffer. Takes care of special control characters,
+    /// such as newlines and carriage returns.
+    fn write_char(&mut self, c: char) {
+        if is_combining_mark(c) {
+            return;
+        }
+        // Anything but a printable char cancels a pending wrap, so e.g. a
+        // newline right after the last column doesn't leave a blank line.
+        let wrap_pending = core::mem::take(&mut self.wrap_pending);
+        match c {
+            '\n' => self.newline(),
+            '\t' if self.direction == TextDirection::Rtl => {
+                // Tab stops are counted from the right border instead
+                let stop_width = self.char_width() * self.tab_size.max(1);
+                let start = self.line_start();
+                let column = (start.saturating_sub(self.x_pos) / stop_width + 1) * stop_width;
+                if column > start.saturating_sub(self.text_left()) {
+                    if self.autowrap {
+                        self.newline();
+                    }
+                } else {
+                    self.x_pos = start - column;
+                }
+            }
+            '\t' => {
+                // Advance to the next tab stop, counted from the left border
+                let stop_width = self.char_width() * self.tab_size.max(1);
+                let column = self.x_pos.saturating_sub(self.text_left());
+                self.x_pos = self.text_left() + (column / stop_width + 1) * stop_width;
+
+                // If the tab stop is beyond the screen width, move to the next line
+                if self.x_pos >= self.text_right() && self.autowrap {
+                    self.newline();
+                }
+            }
+            '\r' => self.carriage_return(),
+            font_constants::BACKSPACE if self.direction == TextDirection::Rtl => self.backspace_rtl(),
+            font_constants::BACKSPACE => self.backspace(),
+            // form feed
+            '\x0c' => self.clear(),
+            // vertical tab: down one line, same column
+            '\x0b' => {
+                self.y_pos += self.line_height();
+                self.scroll_if_at_bottom();
+            }
+            c => {
+                // Look the glyph up once, so the wrap check goes by the width
+                // it's actually drawn with.
+                let raster;
+                let glyph = match self.font {
+                    Some(font) => font.glyph(c),
+                    None => {
+                        raster = get_char_raster(c);
+                        builtin_glyph(raster.as_deref())
+                    }
+                };
+                let glyph_width = self.fixed_advance.unwrap_or(glyph.width() * self.x_scale());
+                let next_width = self.fixed_advance.unwrap_or(self.char_width());
+                if wrap_pending {
+                    self.newline();
+                } else if !self.fits_on_line(glyph_width) {
+                    if !self.autowrap {
+                        return;
+                    }
+                    self.newline();
+                }
+
+                self.scroll_if_at_bottom();
+                if self.direction == TextDirection::Rtl {
+                    // Draw left of the position, then move past the glyph.
+                    let start = self.x_pos.saturating_sub(glyph_width);
+                    self.x_pos = start;
+                    self.record_cell(c);
+                    self.write_rendered_char(glyph);
+                    let gap = if self.fixed_advance.is_some() { 0 } else { self.letter_spacing };
+                    self.x_pos = start.saturating_sub(gap);
+                    if self.autowrap && !self.fits_on_line(next_width) {
+                        self.x_pos = start + glyph_width;
+                        self.wrap_pending = true;
+                    }
+                    return;
+                }
+                let start = self.x_pos;
+                self.record_cell(c);
+                self.write_rendered_char(glyph);
+                if self.autowrap && !self.fits_on_line(next_width) {
+                    self.x_pos = start;
+                    self.wrap_pending = true;
+                }
+            }
+        }
+    }
+
+    /// Scrolls up a line if the current line doesn't fit on the screen.
+    fn scroll_if_at_bottom(&mut self) {
+        if let Some((_, bottom)) = self.scroll_region {
+            if self.cursor_cell().1 == bottom {
+                self.scroll_up(1);
+            }
+            return;
+        }
+        let new_ypos = self.y_pos + self.char_height() + self.border_padding;
+        if new_ypos >= self.text_bottom() {
+            self.scroll_up(1);
+        }
+    }
+
+    /// Moves back one char cell and blanks it. At the start of a line this
+    /// goes back to the last cell of the line above; at the very start of the
+    /// screen it does nothing.
+    fn backspace(&mut self) {
+        let cell_width = self.cell_width();
+        if self.x_pos >= self.text_left() + cell_width {
+            self.x_pos -= cell_width;
+        } else if self.y_pos >= self.text_top() + self.line_height() {
+            self.y_pos -= self.line_height();
+            // Where the line above ended, if we were the ones to end it, or
+            // else the last cell a char fits in.
+            let line_end = self.previous_line_end.take().unwrap_or_else(|| {
+                let free = self.text_right().saturating_sub(self.text_left() + self.char_width() + 1);
+                self.text_left() + (free / cell_width + 1) * cell_width
+            });
+            self.x_pos = line_end.saturating_sub(cell_width).max(self.text_left());
+        } else {
+            return;
+        }
+        self.record_cell(' ');
+        self.draw_filled_rect(self.x_pos, self.y_pos, cell_width, self.char_height(), self.bg_color);
+    }
+
+    /// [Self::backspace] for right-to-left text: moves right one char cell
+    /// and blanks the cell it passes over. At the start of a line this goes
+    /// back to the leftmost cell of the line above.
+    fn backspace_rtl(&mut self) {
+        let cell_width = self.cell_width();
+        let glyph_width = self.fixed_advance.unwrap_or(self.char_width());
+        if self.x_pos + cell_width <= self.line_start() {
+            self.x_pos += cell_width;
+        } else if self.y_pos >= self.text_top() + self.line_height() {
+            self.y_pos -= self.line_height();
+            let line_end = self.previous_line_end.take().unwrap_or_else(|| {
+                let start = self.line_start();
+                let free = start.saturating_sub(self.text_left() + glyph_width);
+                start.saturating_sub((free / cell_width + 1) * cell_width)
+            });
+            self.x_pos = line_end.max(self.text_left()) + cell_width;
+        } else {
+            return;
+        }
+        // The char was recorded where its glyph starts.
+        let end = self.x_pos;
+        self.x_pos = end - glyph_width;
+        self.record_cell(' ');
+        self.x_pos = end;
+        let x = self.x_pos - cell_width;
+        self.draw_filled_rect(x, self.y_pos, cell_width, self.char_height(), self.bg_color);
+    }
+
+    /// Carries out a complete CSI escape sequence. Unsupported sequences are
+    /// ignored.
+    fn handle_csi(&mut self, sequence: &CsiSequence) {
+        match sequence.final_char {
+            'm' => self.apply_sgr(sequence.params()),
+            'J' => match sequence.params().first().copied().unwrap_or(0) {
+                0 => self.clear_to_end_of_screen(),
+                1 => self.clear_from_start_of_screen(),
+                2 => self.clear_screen(),
+                _ => {}
+            },
+            'K' => match sequence.params().first().copied().unwrap_or(0) {
+                0 => self.clear_to_end_of_line(),
+                1 => self.clear_to_start_of_line(),
+                2 => self.clear_line(),
+                _ => {}
+            },
+            // Cursor position, `ESC [ row ; col H`, 1-based with 0 or a
+            // missing parameter counting as 1
+            'H' | 'f' => {
+                let param = |i: usize| sequence.params().get(i).map_or(0, |&n| n.saturating_sub(1) as usize);
+                self.move_to(param(1), param(0));
+            }
+            // Relative cursor movement, `ESC [ n A` and so on, by 1 if n is
+            // 0 or missing
+            'A' | 'B' | 'C' | 'D' => {
+                let n = sequence.params().first().map_or(1, |&n| n.max(1) as usize);
+                match sequence.final_char {
+                    'A' => self.cursor_up(n),
+                    'B' => self.cursor_down(n),
+                    'C' => self.cursor_forward(n),
+                    _ => self.cursor_back(n),
+                }
+            }
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    /// Applies "Select Graphic Rendition" parameters, i.e. color changes.
+    fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.apply_sgr(&[0]);
+            return;
+        }
+        let mut rest = params;
+        while let Some((&param, tail)) = rest.split_first() {
+            rest = tail;
+            match param {
+                0 => {
+                    self.reset_colors();
+                    self.text_attrs = TextAttrs::empty();
+                    self.reverse = false;
+                }
+                4 => self.set_attr(TextAttrs::UNDERLINE),
+                5 => self.set_attr(TextAttrs::BLINK),
+                7 => self.set_reverse(true),
+                9 => self.set_attr(TextAttrs::STRIKETHROUGH),
+                24 => self.clear_attr(TextAttrs::UNDERLINE),
+                25 => self.clear_attr(TextAttrs::BLINK),
+                27 => self.set_reverse(false),
+                29 => self.clear_attr(TextAttrs::STRIKETHROUGH),
+                30..=37 => self.set_text_color(palette_color((param - 30) as u8)),
+                38 | 48 => {
+                    // Extended color, e.g. `38;2;r;g;b`. A malformed one
+                    // ends the sequence, since its remaining parameters
+                    // can't be told apart from unrelated codes.
+                    let Some((color, tail)) = parse_extended_color(rest) else {
+                        return;
+                    };
+                    rest = tail;
+                    if param == 38 {
+                        self.set_text_color(color);
+                    } else {
+                        self.set_bg_color(color);
+                    }
+                }
+                39 => self.set_text_color(self.default_colors.0),
+                40..=47 => self.set_bg_color(palette_color((param - 40) as u8)),
+                49 => self.set_bg_color(self.default_colors.1),
+                90..=97 => self.set_text_color(palette_color((param - 90 + 8) as u8)),
+                100..=107 => self.set_bg_color(palette_color((param - 100 + 8) as u8)),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the current font can draw `c` without falling back.
+    fn has_glyph(&self, c: char) -> bool {
+        match self.font {
+            Some(font) => font.contains(c),
+            None => get_raster(c, FONT_WEIGHT, CHAR_RASTER_HEIGHT).is_some(),
+        }
+    }
+
+    /// Draws `c` at the write position in the current font.
+    /// Updates self.x_pos.
+    fn draw_char(&mut self, c: char) {
+        match self.font {
+            Some(font) => self.write_rendered_char(font.glyph(c)),
+            None => self.write_rendered_char(builtin_glyph(get_char_raster(c).as_deref())),
+        }
+    }
+
+    /// Prints a rendered char into the framebuffer.
+    /// Updates self.x_pos.
+    fn write_rendered_char(&mut self, glyph: Glyph) {
+        let (x_scale, y_scale) = (self.x_scale(), self.y_scale());
+        let glyph_width = glyph.width() * x_scale;
+        let (fg, bg) = self.effective_colors();
+        let start = self.x_pos;
+        if let Some(advance) = self.fixed_advance {
+            // Center the glyph in its cell and give the sides the background.
+            if self.opaque_background || self.reverse {
+                self.draw_filled_rect(start, self.y_pos, advance, self.char_height(), bg);
+            }
+            self.x_pos += advance.saturating_sub(glyph_width) / 2;
+        }
+        if self.text_effect == TextEffect::None {
+            for y in 0..glyph.height() {
+                for x in 0..glyph.width() {
+                    let intensity = glyph.intensity(x, y);
+                    // each raster pixel becomes an x_scale x y_scale block
+                    for dy in 0..y_scale {
+                        for dx in 0..x_scale {
+                            self.write_pixel(self.x_pos + x * x_scale + dx, self.y_pos + y * y_scale + dy, intensity);
+                        }
+                    }
+                }
+            }
+        } else {
+            // The glyph's own background would paint over the effect, so it
+            // goes first and the glyphs only draw their covered pixels.
+            if self.opaque_background || self.reverse {
+                self.draw_filled_rect(self.x_pos, self.y_pos, glyph_width, self.char_height(), bg);
+            }
+            match self.text_effect {
+                TextEffect::Shadow(color, dx, dy) => self.draw_glyph_layer(glyph, color, dx, dy),
+                TextEffect::Outline(color) => {
+                    for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                        self.draw_glyph_layer(glyph, color, dx, dy);
+                    }
+                }
+                TextEffect::None => {}
+            }
+            self.draw_glyph_layer(glyph, fg, 0, 0);
+        }
+        if self.text_attrs.contains(TextAttrs::UNDERLINE) {
+            let y = self.y_pos + self.char_height() - y_scale;
+            self.draw_filled_rect(self.x_pos, y, glyph_width, y_scale, fg);
+        }
+        if self.text_attrs.contains(TextAttrs::STRIKETHROUGH) {
+            let y = self.y_pos + self.char_height() / 2;
+            self.draw_filled_rect(self.x_pos, y, glyph_width, y_scale, fg);
+        }
+        self.x_pos = start + self.fixed_advance.unwrap_or(glyph_width + self.letter_spacing);
+    }
+
+    /// Draws only the covered pixels of a glyph in `color`, offset by
+    /// (dx, dy) from the write position, without moving it.
+    fn draw_glyph_layer(&mut self, glyph: Glyph, color: [u8; 3], dx: isize, dy: isize) {
+        let (x_scale, y_scale) = (self.x_scale(), self.y_scale());
+        let bg = if self.opaque_background || self.reverse { self.effective_colors().1 } else { [0, 0, 0] };
+        let (left, top) = (self.x_pos as isize + dx, self.y_pos as isize + dy);
+        for y in 0..glyph.height() {
+            for x in 0..glyph.width() {
+                let intensity = glyph.intensity(x, y);
+                if intensity == 0 {
+                    continue;
+                }
+                let pixel = self.pixel_bytes([0, 1, 2].map(|i| blend_channel(bg[i], color[i], intensity)));
+                for sy in 0..y_scale {
+                    for sx in 0..x_scale {
+                        let px = left + (x * x_scale + sx) as isize;
+                        let py = top + (y * y_scale + sy) as isize;
+                        if px >= 0 && py >= 0 {
+                            self.write_pixel_bytes(px as usize, py as usize, pixel);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+
+
+   
+    
+    fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
+        // Reverse video needs the whole cell painted to show up at all.
+        let opaque = self.opaque_background || self.reverse;
+        if intensity == 0 && !opaque {
+            // Skip rendering for the background
+            return;
+        }
+
+        let (fg, bg) = self.effective_colors();
+        let bg = if opaque { bg } else { [0, 0, 0] };
+        let rgb = [0, 1, 2].map(|i| blend_channel(bg[i], fg[i], intensity));
+
+        // On U8 this comes out as the luminance of the blend, so grayscale
+        // glyphs stay antialiased and never get brighter than the text color.
+        let color = self.pixel_bytes(rgb);
+        self.write_pixel_bytes(x, y, color);
+    }
+
+    /// The (foreground, background) colors glyphs are drawn with, which are
+    /// swapped in reverse video.
+    fn effective_colors(&self) -> ([u8; 3], [u8; 3]) {
+        if self.reverse {
+            (self.bg_color, self.text_color)
+        } else {
+            (self.text_color, self.bg_color)
+        }
+    }
+
+    /// Sets the pixel at (x, y) to the given RGB color, converted to the
+    /// framebuffer's pixel format. Returns false if (x, y) is off-screen.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) -> bool {
+        let color = self.pixel_bytes(color);
+        self.write_pixel_bytes(x, y, color)
+    }
+
+    /// Converts an RGB color into the byte layout of the framebuffer, scaled
+    /// by the brightness.
+    fn pixel_bytes(&self, color: [u8; 3]) -> [u8; 4] {
+        Color::from(color).to_bytes(self.info.pixel_format, self.info.bytes_per_pixel, self.brightness)
+    }
+
+    /// Reads back the pixel at (x, y) in the framebuffer's byte layout.
+    /// Returns None if (x, y) is off-screen.
+    fn read_pixel_bytes(&self, x: usize, y: usize) -> Option<[u8; 4]> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let (x, y) = self.physical(x, y);
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let byte_offset = (y * self.info.stride + x) * bytes_per_pixel;
+        let (buffer, byte_offset) = self.pixel_source(byte_offset, bytes_per_pixel);
+        let pixel = buffer.get(byte_offset..byte_offset + bytes_per_pixel)?;
+        let mut color = [0; 4];
+        color[..bytes_per_pixel].copy_from_slice(pixel);
+        Some(color)
+    }
+
+    /// Copies an already converted pixel into the framebuffer.
+    /// Does nothing and returns false if (x, y) is off-screen.
+    fn write_pixel_bytes(&mut self, x: usize, y: usize, color: [u8; 4]) -> bool {
+        if x >= self.width() || y >= self.height() {
+            return false;
+        }
+        let (x, y) = self.physical(x, y);
+        self.mark_dirty(x, y, x + 1, y + 1);
+
+        let pixel_offset = y * self.info.stride + x;
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let (buffer, byte_offset) = self.pixel_target(pixel_offset * bytes_per_pixel, bytes_per_pixel);
+        #[cfg(not(feature = "unchecked"))]
+        let pixel = &mut buffer[byte_offset..(byte_offset + bytes_per_pixel)];
+        #[cfg(feature = "unchecked")]
+        let pixel = {
+            debug_assert!(byte_offset + bytes_per_pixel <= buffer.len());
+            // SAFETY: (x, y) is on screen, and the constructors made sure the
+            // buffers are long enough for every on-screen pixel. Staged
+            // pixels are inside the staged range.
+            unsafe { buffer.get_unchecked_mut(byte_offset..(byte_offset + bytes_per_pixel)) }
+        };
+        // Volatile writes keep the compiler from dropping or merging stores
+        // to the (memory-mapped) framebuffer. Nothing is read back, so
+        // drawing a pixel costs only the stores themselves.
+        for (dst, &byte) in pixel.iter_mut().zip(&color[..bytes_per_pixel]) {
+            // SAFETY: `dst` comes from a live mutable borrow of the buffer.
+            unsafe { ptr::write_volatile(dst, byte) };
+        }
+        true
+    }
+}
+
+/// The writer used by [print!] and [println!]. Empty until [init] is called.
+pub static WRITER: Mutex<Option<StaticFrameBufferWriter>> = Mutex::new(None);
+
+/// The text of [WRITER]. It's kept in a static rather than on the stack the
+/// writer is created on, and handed out once, to the first [init].
+static WRITER_TEXT: Mutex<TextBuffer> = Mutex::new(TextBuffer::new());
+
+/// Creates the global writer over the given framebuffer and clears the
+/// screen. Called again, it moves the writer over to the new framebuffer
+/// like [FrameBufferWriter::reinit] and clears it.
+pub fn init(framebuffer: &'static mut [u8], info: FrameBufferInfo) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        match writer.as_mut() {
+            Some(writer) => {
+                writer.reinit(framebuffer, info);
+                writer.clear();
+            }
+            None => {
+                let text = MutexGuard::leak(WRITER_TEXT.lock());
+                *writer = Some(FrameBufferWriter::new_in(framebuffer, info, text));
+            }
+        }
+    });
+}
+
+/// Prints to the global writer, if it was initialized.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    // An interrupt handler that prints while we hold the lock would spin
+    // forever, so keep interrupts off for as long as we have it.
+    x86_64::instructions::interrupts::without_interrupts(|| print_to(&WRITER, args));
+}
+
+/// What [_print] does with interrupts off: locks `writer` and prints to it,
+/// if it was initialized.
+fn print_to(writer: &Mutex<Option<FrameBufferWriter>>, args: fmt::Arguments) {
+    if let Some(writer) = writer.lock().as_mut() {
+        let _ = writer.write_fmt(args);
+    }
+}
+
+/// Shows a panic report on screen through the global writer, if it was
+/// initialized. Meant to be called from the `#[panic_handler]`.
+pub fn print_panic(info: &core::panic::PanicInfo) {
+    x86_64::instructions::interrupts::disable();
+    // The panic may have happened while the writer was locked, and whoever
+    // held the lock is never going to release it.
+    if WRITER.is_locked() {
+        // SAFETY: interrupts are off and the kernel is single core, so the
+        // holder of the lock can't be running anymore.
+        unsafe { WRITER.force_unlock() };
+    }
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.render_panic(info);
+    }
+}
+
+/// Prints to the screen through the global [WRITER].
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::writer::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the screen through the global [WRITER], with a newline.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+impl Write for FrameBufferWriter<'_> {
+    /// `s` is a `str` and so always valid UTF-8; raw bytes go through
+    /// [FrameBufferWriter::write_bytes] instead. Chars the font has no glyph
+    /// for show up as [font_constants::BACKUP_CHAR] and take up a cell like
+    /// any other, and combining marks are skipped without moving on.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_chars(s.chars());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{format, string::String};
+
+    use super::mock::MockFrameBuffer;
+    use super::*;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+    const CHAR_WIDTH: usize = font_constants::CHAR_RASTER_WIDTH;
+    const CHAR_HEIGHT: usize = CHAR_RASTER_HEIGHT.val();
+    const LINE_HEIGHT: usize = CHAR_HEIGHT + LINE_SPACING;
+
+    /// Big enough for the default 80x40 screen and anything smaller.
+    type Mock = MockFrameBuffer<{ WIDTH * HEIGHT * 4 }>;
+    /// Room for 4 text rows.
+    type TallMock = MockFrameBuffer<{ WIDTH * 80 * 4 }>;
+
+    fn mock() -> Mock {
+        Mock::new(PixelFormat::Rgb, WIDTH, HEIGHT)
+    }
+
+    fn tall_mock() -> TallMock {
+        TallMock::new(PixelFormat::Rgb, WIDTH, 80)
+    }
+
+    /// A mock whose text rows are exactly `columns` cells wide.
+    fn narrow_mock(columns: usize) -> Mock {
+        Mock::new(PixelFormat::Rgb, 2 * BORDER_PADDING + columns * CHAR_WIDTH, HEIGHT)
+    }
+
+    /// The checksum of what `draw` leaves on a fresh default screen.
+    fn checksum_of(draw: impl FnOnce(&mut FrameBufferWriter)) -> u64 {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        draw(&mut writer);
+        writer.checksum()
+    }
+
+    /// How many pixels of the `w` x `h` rectangle at (x, y) aren't black.
+    fn lit<const N: usize>(fb: &MockFrameBuffer<N>, x: usize, y: usize, w: usize, h: usize) -> usize {
+        (y..y + h).flat_map(|y| (x..x + w).map(move |x| (x, y))).filter(|&(x, y)| fb.pixel_at(x, y) != [0; 3]).count()
+    }
+
+    /// The chars of text row `row`, without the empty cells at the end.
+    fn row_text(writer: &FrameBufferWriter, row: usize) -> String {
+        let text: String = (0..writer.screen_columns()).filter_map(|col| writer.char_at(col, row)).collect();
+        String::from(text.trim_end())
+    }
+
+    /// The first pixel of `c`'s raster that is neither empty nor fully
+    /// covered, as (x, y, intensity).
+    fn antialiased_pixel(c: char) -> (usize, usize, u8) {
+        let raster = get_char_raster(c).unwrap();
+        (0..raster.height())
+            .flat_map(|y| (0..raster.width()).map(move |x| (x, y)))
+            .map(|(x, y)| (x, y, raster.raster()[y][x]))
+            .find(|&(_, _, intensity)| intensity > 0 && intensity < 255)
+            .unwrap()
+    }
+
+    #[test]
+    fn draws_one_glyph_per_char() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("abcdef").unwrap();
+        assert_eq!(row_text(&writer, 0), "abcdef");
+        assert_eq!(writer.cursor(), (BORDER_PADDING + 6 * CHAR_WIDTH, BORDER_PADDING));
+        for i in 0..6 {
+            assert!(lit(&fb, 1 + i * CHAR_WIDTH, 1, CHAR_WIDTH, CHAR_HEIGHT) > 0, "cell {} is empty", i);
+        }
+        assert_eq!(lit(&fb, 1 + 6 * CHAR_WIDTH, 0, WIDTH - 1 - 6 * CHAR_WIDTH, HEIGHT), 0);
+        // The default text color is white, so every pixel comes out gray.
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let [r, g, b] = fb.pixel_at(x, y);
+                assert!(r == g && g == b, "({}, {}) is colored", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn antialiasing_scales_every_channel() {
+        let (x, y, intensity) = antialiased_pixel('a');
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_text_color([200, 100, 50]);
+        writer.write_str("a").unwrap();
+        let expected = [200, 100, 50].map(|c: u16| (c * intensity as u16 / 255) as u8);
+        assert_eq!(fb.pixel_at(BORDER_PADDING + x, BORDER_PADDING + y), expected);
+    }
+
+    #[test]
+    fn renders_the_raster_exactly() {
+        let raster = get_char_raster('A').unwrap();
+        let mut fb = mock();
+        fb.writer().write_str("A").unwrap();
+        for (y, row) in raster.raster().iter().enumerate() {
+            for (x, &intensity) in row.iter().enumerate() {
+                assert_eq!(fb.pixel_at(1 + x, 1 + y), [intensity; 3], "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_up_moves_text_and_cursor() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("A\nB").unwrap();
+        writer.scroll_up(1);
+        assert_eq!(writer.char_at(0, 0), Some('B'));
+        assert_eq!(writer.char_at(0, 1), Some(' '));
+        assert_eq!(writer.cursor(), (1 + CHAR_WIDTH, 1));
+        assert_eq!(writer.checksum(), checksum_of(|w| w.write_str("B").unwrap()));
+
+        // Scrolling further than the screen is high just clears it.
+        writer.scroll_up(5);
+        assert_eq!(writer.cursor(), (1, 1));
+        assert_eq!(writer.char_at(0, 0), Some(' '));
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn scrolling_fills_with_the_background_color() {
+        const BLUE: [u8; 3] = [0, 0, 255];
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("a\nb").unwrap();
+        writer.set_bg_color(BLUE);
+        writer.scroll_up(1);
+        writer.scroll_pixels_left(3);
+        assert_eq!(fb.pixel_at(0, HEIGHT - 1), BLUE);
+        assert_eq!(fb.pixel_at(WIDTH - 1, 0), BLUE);
+    }
+
+    #[test]
+    fn overwriting_leaves_no_residue() {
+        let overwritten = checksum_of(|w| w.write_str("88\r11").unwrap());
+        assert_eq!(overwritten, checksum_of(|w| w.write_str("11").unwrap()));
+    }
+
+    #[test]
+    fn set_pixel_rejects_off_screen_coordinates() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        assert!(!writer.set_pixel(WIDTH, 0, [255; 3]));
+        assert!(!writer.set_pixel(0, HEIGHT, [255; 3]));
+        assert!(!writer.set_pixel(usize::MAX, usize::MAX, [255; 3]));
+        assert!(writer.set_pixel(WIDTH - 1, HEIGHT - 1, [255, 0, 0]));
+        assert_eq!(fb.pixel_at(WIDTH - 1, HEIGHT - 1), [255, 0, 0]);
+    }
+
+    #[test]
+    fn reports_size_and_cursor() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        assert_eq!(writer.dimensions(), (WIDTH, HEIGHT));
+        assert_eq!(writer.info().width, WIDTH);
+        assert_eq!(writer.cursor(), (BORDER_PADDING, BORDER_PADDING));
+        writer.write_str("ab").unwrap();
+        assert_eq!(writer.cursor(), (BORDER_PADDING + 2 * CHAR_WIDTH, BORDER_PADDING));
+        assert_eq!((writer.columns(), writer.rows()), (11, 2));
+    }
+
+    #[test]
+    fn labels_leave_the_stream_alone() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("AB").unwrap();
+        let cursor = writer.cursor();
+        writer.write_str_at(30, 20, "x\ny");
+        // Past the edges, labels are clipped instead of wrapping or scrolling.
+        writer.write_str_at(WIDTH - 3, HEIGHT - 5, "a label far wider than the screen");
+        writer.write_str_at(usize::MAX, usize::MAX, "gone");
+        assert_eq!(writer.cursor(), cursor);
+        assert_eq!(writer.char_at(0, 0), Some('A'));
+        assert_eq!(row_text(&writer, 1), "");
+        writer.write_str("C").unwrap();
+        assert_eq!(row_text(&writer, 0), "ABC");
+        assert!(lit(&fb, 30, 20, CHAR_WIDTH, CHAR_HEIGHT) > 0);
+    }
+
+    #[test]
+    fn sgr_sets_colors() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("\x1b[31mA\x1b[0mB").unwrap();
+        assert_eq!(row_text(&writer, 0), "AB");
+        assert_eq!(writer.cells[0][0].fg, palette_color(1));
+        assert_eq!(writer.cells[0][1].fg, DEFAULT_TEXT_COLOR);
+
+        // A sequence split across two writes still counts.
+        writer.write_str("\x1b[3").unwrap();
+        writer.write_str("2mC").unwrap();
+        assert_eq!(row_text(&writer, 0), "ABC");
+        assert_eq!(writer.cells[0][2].fg, palette_color(2));
+
+        writer.write_str("\x1b[44;97mD\x1b[39;49mE").unwrap();
+        assert_eq!((writer.cells[0][3].fg, writer.cells[0][3].bg), (palette_color(15), palette_color(4)));
+        assert_eq!((writer.cells[0][4].fg, writer.cells[0][4].bg), (DEFAULT_TEXT_COLOR, DEFAULT_BG_COLOR));
+    }
+
+    #[test]
+    fn sgr_sets_extended_colors() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("\x1b[38;2;255;128;0mA\x1b[48;2;0;0;255mB\x1b[38;2;300;0;0mC").unwrap();
+        assert_eq!(writer.cells[0][0].fg, [255, 128, 0]);
+        assert_eq!(writer.cells[0][1].bg, [0, 0, 255]);
+        assert_eq!(writer.cells[0][2].fg, [255, 0, 0]);
+        writer.write_str("\x1b[38;5;196mD\x1b[48;5;21mE").unwrap();
+        assert_eq!(writer.cells[0][3].fg, [255, 0, 0]);
+        assert_eq!(writer.cells[0][4].bg, [0, 0, 255]);
+
+        // A malformed color is dropped without printing its parameters.
+        writer.write_str("\x1b[38;2;1mF").unwrap();
+        assert_eq!(row_text(&writer, 0), "ABCDEF");
+        assert_eq!(writer.cells[0][5].fg, [255, 0, 0]);
+    }
+
+    #[test]
+    fn cursor_shapes_cover_their_part_of_the_cell() {
+        let cases = [
+            (CursorShape::Block, (0, 0, CHAR_WIDTH, CHAR_HEIGHT)),
+            (CursorShape::Underline, (0, CHAR_HEIGHT - CURSOR_THICKNESS, CHAR_WIDTH, CURSOR_THICKNESS)),
+            (CursorShape::Bar, (0, 0, CURSOR_THICKNESS, CHAR_HEIGHT)),
+        ];
+        for (shape, (x, y, w, h)) in cases {
+            let mut fb = mock();
+            let mut writer = fb.writer();
+            writer.set_cursor_shape(shape);
+            writer.draw_cursor();
+            assert_eq!(lit(&fb, 1 + x, 1 + y, w, h), w * h, "{:?}", shape);
+            assert_eq!(lit(&fb, 0, 0, WIDTH, HEIGHT), w * h, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn erasing_the_cursor_redraws_the_char_under_it() {
+        let full_line = "abcdefgh";
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.write_str(full_line).unwrap();
+        let expected = writer.checksum();
+        // The wrap is pending, so the cursor sits on the last char.
+        writer.draw_cursor();
+        assert_ne!(writer.checksum(), expected);
+        writer.erase_cursor();
+        assert_eq!(writer.checksum(), expected);
+        writer.toggle_cursor();
+        writer.toggle_cursor();
+        assert_eq!(writer.checksum(), expected);
+    }
+
+    #[test]
+    fn saves_and_restores_the_cursor() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("ab\x1b[scd\x1b[uX").unwrap();
+        assert_eq!(row_text(&writer, 0), "abXd");
+        // Without a saved position, restoring does nothing.
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("ab").unwrap();
+        writer.restore_cursor();
+        assert_eq!(writer.cursor(), (1 + 2 * CHAR_WIDTH, 1));
+    }
+
+    #[test]
+    fn back_buffer_shows_up_on_present() {
+        let mut back = [0; WIDTH * HEIGHT * 4];
+        let mut fb = mock();
+        let mut writer = fb.writer_with_back_buffer(&mut back);
+        let blank = writer.checksum();
+        writer.write_str("A").unwrap();
+        assert_eq!(writer.checksum(), blank);
+        writer.present();
+        assert_ne!(writer.checksum(), blank);
+        assert_eq!(fb.bytes(), &back[..]);
+    }
+
+    #[test]
+    fn present_region_copies_only_the_region() {
+        let mut back = [0; WIDTH * HEIGHT * 4];
+        let mut fb = mock();
+        let mut writer = fb.writer_with_back_buffer(&mut back);
+        writer.draw_filled_rect(0, 0, 10, 10, [255, 0, 0]);
+        writer.draw_filled_rect(50, 20, 10, 10, [0, 255, 0]);
+        writer.present_region(0, 0, 10, 10);
+        writer.present_region(WIDTH, HEIGHT, usize::MAX, usize::MAX);
+        assert_eq!(fb.pixel_at(9, 9), [255, 0, 0]);
+        assert_eq!(lit(&fb, 0, 0, 10, 10), 100);
+        assert_eq!(lit(&fb, 10, 0, WIDTH - 10, HEIGHT), 0);
+    }
+
+    #[test]
+    fn try_new_rejects_unsupported_formats() {
+        let mut fb = mock();
+        let info = FrameBufferInfo {
+            pixel_format: PixelFormat::Unknown { red_position: 30, green_position: 0, blue_position: 8 },
+            ..fb.info()
+        };
+        let (bytes, _) = fb.parts();
+        let result = FrameBufferWriter::try_new(bytes, info).err();
+        assert_eq!(result, Some(WriterError::UnsupportedPixelFormat(info.pixel_format)));
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn draws_rgb565() {
+        let info = FrameBufferInfo {
+            byte_len: WIDTH * HEIGHT * 2,
+            width: WIDTH,
+            height: HEIGHT,
+            pixel_format: PixelFormat::Unknown { red_position: 11, green_position: 5, blue_position: 0 },
+            bytes_per_pixel: 2,
+            stride: WIDTH,
+        };
+        let mut fb = Mock::with_info(info);
+        let mut writer = fb.writer();
+        writer.set_pixel(0, 0, [255, 128, 0]);
+        writer.set_brightness(128);
+        writer.set_pixel(1, 0, [255, 128, 0]);
+        writer.set_brightness(255);
+        writer.write_str("\nA").unwrap();
+        assert_eq!(fb.bytes()[..4], [0x00, 0xfc, 0x00, 0x82]);
+        assert!(lit(&fb, 1, 1 + LINE_HEIGHT, CHAR_WIDTH, CHAR_HEIGHT) > 0);
+    }
+
+    #[test]
+    fn draws_channels_at_any_position() {
+        let formats = [
+            (4, PixelFormat::Unknown { red_position: 16, green_position: 24, blue_position: 0 }, [0x33, 0, 0x11, 0x22]),
+            (3, PixelFormat::Unknown { red_position: 0, green_position: 8, blue_position: 16 }, [0x11, 0x22, 0x33, 0]),
+        ];
+        for (bytes_per_pixel, pixel_format, expected) in formats {
+            let info = FrameBufferInfo {
+                byte_len: WIDTH * HEIGHT * bytes_per_pixel,
+                width: WIDTH,
+                height: HEIGHT,
+                pixel_format,
+                bytes_per_pixel,
+                stride: WIDTH,
+            };
+            let mut fb = Mock::with_info(info);
+            fb.writer().set_pixel(0, 0, [0x11, 0x22, 0x33]);
+            assert_eq!(fb.bytes()[..bytes_per_pixel], expected[..bytes_per_pixel], "{:?}", pixel_format);
+            assert_eq!(fb.pixel_at(0, 0), [0x11, 0x22, 0x33]);
+        }
+    }
+
+    #[test]
+    fn println_renders_the_digits() {
+        let expected = checksum_of(|w| w.write_str("42\n").unwrap());
+        let mut fb = mock();
+        let writer = Mutex::new(Some(fb.writer()));
+        // What `println!("{}", 42)` hands to _print.
+        print_to(&writer, format_args!("{}\n", format_args!("{}", 42)));
+        let writer = writer.into_inner().unwrap();
+        assert_eq!(row_text(&writer, 0), "42");
+        assert_eq!(writer.cursor(), (BORDER_PADDING, BORDER_PADDING + LINE_HEIGHT));
+        assert_eq!(writer.checksum(), expected);
+        // Nothing happens before the writer is set up.
+        print_to(&Mutex::new(None), format_args!("{}", 42));
+    }
+
+    #[test]
+    fn word_wrap_moves_whole_words() {
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.write_str("abc defgh").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("abc defg", "h"));
+
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.set_wrap_mode(WrapMode::Word);
+        writer.write_str("abc defgh").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("abc", "defgh"));
+
+        // Words longer than a line are still broken.
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.set_wrap_mode(WrapMode::Word);
+        writer.write_str("abcdefghij").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("abcdefgh", "ij"));
+    }
+
+    #[test]
+    fn tabs_go_to_the_next_stop() {
+        let stop = DEFAULT_TAB_SIZE * CHAR_WIDTH;
+        for prefix in ["", "a", "ab", "abc"] {
+            let mut fb = mock();
+            let mut writer = fb.writer();
+            writer.write_str(prefix).unwrap();
+            writer.write_str("\t").unwrap();
+            assert_eq!(writer.cursor(), (1 + stop, 1), "after {:?}", prefix);
+        }
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("abcd\t").unwrap();
+        assert_eq!(writer.cursor(), (1 + 2 * stop, 1));
+
+        // A stop past the right edge starts a new line.
+        let mut fb = Mock::new(PixelFormat::Rgb, 1 + stop, HEIGHT);
+        let mut writer = fb.writer();
+        writer.write_str("x\t").unwrap();
+        assert_eq!(writer.cursor(), (1, 1 + LINE_HEIGHT));
+    }
+
+    #[test]
+    fn spacing_changes_the_advance() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_line_spacing(0);
+        writer.write_str("a\nb").unwrap();
+        assert_eq!(writer.cursor(), (1 + CHAR_WIDTH, 1 + CHAR_HEIGHT));
+
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_letter_spacing(3);
+        writer.write_str("ab").unwrap();
+        assert_eq!(writer.cursor(), (1 + 2 * (CHAR_WIDTH + 3), 1));
+        assert_eq!(writer.char_at(1, 0), Some('b'));
+    }
+
+    #[test]
+    fn scale_multiplies_the_glyph() {
+        let mut fb = mock();
+        fb.writer().write_str("A").unwrap();
+        let normal = lit(&fb, 0, 0, WIDTH, HEIGHT);
+
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_scale(2);
+        writer.write_str("A").unwrap();
+        assert_eq!(writer.cursor(), (1 + 2 * CHAR_WIDTH, 1));
+        assert_eq!(lit(&fb, 0, 0, WIDTH, HEIGHT), 4 * normal);
+    }
+
+    #[test]
+    fn decorations_draw_lines_across_the_cell() {
+        let underline_row = 1 + CHAR_HEIGHT - 1;
+        let strike_row = 1 + CHAR_HEIGHT / 2;
+        let mut fb = mock();
+        fb.writer().write_str("a").unwrap();
+        assert!(lit(&fb, 1, underline_row, CHAR_WIDTH, 1) < CHAR_WIDTH);
+
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_attr(TextAttrs::UNDERLINE | TextAttrs::STRIKETHROUGH);
+        writer.write_str("a").unwrap();
+        writer.clear_attr(TextAttrs::UNDERLINE | TextAttrs::STRIKETHROUGH);
+        writer.write_str("a").unwrap();
+        assert_eq!(lit(&fb, 1, underline_row, CHAR_WIDTH, 1), CHAR_WIDTH);
+        assert_eq!(lit(&fb, 1, strike_row, CHAR_WIDTH, 1), CHAR_WIDTH);
+        assert!(lit(&fb, 1 + CHAR_WIDTH, underline_row, CHAR_WIDTH, 1) < CHAR_WIDTH);
+    }
+
+    #[test]
+    fn reverse_video_fills_the_cell() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_reverse(true);
+        writer.write_str(" ").unwrap();
+        assert_eq!((writer.cells[0][0].fg, writer.cells[0][0].bg), (DEFAULT_BG_COLOR, DEFAULT_TEXT_COLOR));
+        assert_eq!(lit(&fb, 1, 1, CHAR_WIDTH, CHAR_HEIGHT), CHAR_WIDTH * CHAR_HEIGHT);
+        assert_eq!(fb.pixel_at(1, 1), DEFAULT_TEXT_COLOR);
+    }
+
+    #[test]
+    fn erases_parts_of_a_line() {
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.write_str("abcdefgh").unwrap();
+        writer.move_to(4, 0);
+        writer.clear_to_end_of_line();
+        assert_eq!(row_text(&writer, 0), "abcd");
+        assert_eq!(lit(&fb, 1 + 4 * CHAR_WIDTH, 0, 4 * CHAR_WIDTH, HEIGHT), 0);
+        assert!(lit(&fb, 1 + 3 * CHAR_WIDTH, 1, CHAR_WIDTH, CHAR_HEIGHT) > 0);
+
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.write_str("abcdefgh\x1b[1;3H\x1b[1K").unwrap();
+        assert_eq!(row_text(&writer, 0), "   defgh");
+        writer.write_str("\x1b[2K").unwrap();
+        assert_eq!(row_text(&writer, 0), "");
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn erases_parts_of_the_screen() {
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        writer.write_str("aa\nbb\ncc\x1b[2;2H\x1b[J").unwrap();
+        assert_eq!([0, 1, 2].map(|row| row_text(&writer, row)), ["aa", "b", ""]);
+        writer.write_str("\x1b[1J").unwrap();
+        assert_eq!([0, 1, 2].map(|row| row_text(&writer, row)), ["", "", ""]);
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn measures_without_drawing() {
+        let mut fb = mock();
+        let writer = fb.writer();
+        assert_eq!(writer.measure_str("a"), (CHAR_WIDTH, CHAR_HEIGHT));
+        assert_eq!(writer.measure_str("abc"), (3 * CHAR_WIDTH, CHAR_HEIGHT));
+        assert_eq!(writer.measure_str("ab\nabcd"), (4 * CHAR_WIDTH, LINE_HEIGHT + CHAR_HEIGHT));
+        assert_eq!(writer.measure_str("a\t"), (DEFAULT_TAB_SIZE * CHAR_WIDTH, CHAR_HEIGHT));
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn aligns_text_on_the_line() {
+        let text_width = 2 * CHAR_WIDTH;
+        let centered = checksum_of(|w| w.write_centered(10, "ab"));
+        assert_eq!(centered, checksum_of(|w| w.write_str_at((WIDTH - text_width) / 2, 10, "ab")));
+        let right = checksum_of(|w| w.write_right_aligned(10, "ab"));
+        assert_eq!(right, checksum_of(|w| w.write_str_at(WIDTH - 1 - text_width, 10, "ab")));
+        // Too wide to fit, they start at the left border.
+        let wide = "a very long line";
+        assert_eq!(checksum_of(|w| w.write_centered(10, wide)), checksum_of(|w| w.write_str_at(1, 10, wide)));
+        assert_eq!(checksum_of(|w| w.write_right_aligned(10, wide)), checksum_of(|w| w.write_str_at(1, 10, wide)));
+    }
+
+    #[test]
+    fn hexdump_lines() {
+        let mut fb = MockFrameBuffer::<{ 530 * HEIGHT * 4 }>::new(PixelFormat::Rgb, 530, HEIGHT);
+        let mut writer = fb.writer();
+        writer.hexdump(0x10, b"0123456789abcdef\x00\x01\xff ");
+        assert_eq!(row_text(&writer, 0), "00000010: 30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  0123456789abcdef");
+        assert_eq!(row_text(&writer, 1), format!("00000020: 00 01 ff 20{}...", " ".repeat(38)));
+    }
+
+    #[test]
+    fn hexdump_addresses_wrap_around() {
+        let mut fb = MockFrameBuffer::<{ 530 * HEIGHT * 4 }>::new(PixelFormat::Rgb, 530, HEIGHT);
+        let mut writer = fb.writer();
+        writer.hexdump(usize::MAX - 4, &[0; 20]);
+        assert_eq!(writer.find("0000000b: 00 00 00 00"), Some(TextPosition::Screen { col: 0, row: 1 }));
+    }
+
+    #[test]
+    fn panic_report() {
+        let location = core::panic::Location::caller();
+        let mut fb = MockFrameBuffer::<{ 400 * 80 * 4 }>::new(PixelFormat::Rgb, 400, 80);
+        let mut writer = fb.writer();
+        writer.write_str("\x1b[4;31mwhatever was there").unwrap();
+        writer.render_panic_report("boom", Some(location));
+        assert_eq!(row_text(&writer, 0), "KERNEL PANIC");
+        assert_eq!(row_text(&writer, 1), "boom");
+        assert_eq!(row_text(&writer, 2), format!("at {}:{}:{}", location.file(), location.line(), location.column()));
+        assert_eq!(writer.cells[1][0].fg, DEFAULT_TEXT_COLOR);
+        assert_eq!(fb.pixel_at(399, 79), PANIC_BG_COLOR);
+    }
+
+    #[test]
+    fn backspace_erases_the_previous_char() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("ab\x08").unwrap();
+        assert_eq!(writer.cursor(), (1 + CHAR_WIDTH, 1));
+        assert_eq!(row_text(&writer, 0), "a");
+        writer.write_str("\x08").unwrap();
+        assert!(fb.is_blank());
+
+        // At the start of a line it goes back to the end of the line above.
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("a\nb\x08\x08").unwrap();
+        assert_eq!(writer.cursor(), (1, 1));
+        assert_eq!([row_text(&writer, 0), row_text(&writer, 1)], ["", ""]);
+        // At the very start it does nothing
+        writer.write_str("\x08").unwrap();
+        assert_eq!(writer.cursor(), (1, 1));
+        writer.repaint();
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn form_feed_and_vertical_tab() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("abc\x0c").unwrap();
+        assert_eq!((writer.cursor(), row_text(&writer, 0).as_str()), ((1, 1), ""));
+        writer.write_str("ab\x0bc").unwrap();
+        assert_eq!(row_text(&writer, 0), "ab");
+        assert_eq!(row_text(&writer, 1), "  c");
+    }
+
+    #[test]
+    fn draws_into_a_plain_buffer() {
+        const W: usize = 16;
+        const H: usize = 20;
+        let mut framebuffer = [0; W * H * 4];
+        let mut text = TextBuffer::new();
+        let info = FrameBufferInfo {
+            byte_len: framebuffer.len(),
+            width: W,
+            height: H,
+            pixel_format: PixelFormat::Bgr,
+            bytes_per_pixel: 4,
+            stride: W,
+        };
+        let mut writer = FrameBufferWriter::new_in(&mut framebuffer, info, &mut text);
+        writer.write_str("a").unwrap();
+        assert!(framebuffer.iter().any(|&byte| byte != 0));
+    }
+
+    #[test]
+    fn builtin_text_buffers_run_out() {
+        // The built-in text buffers are never given back, so this is the
+        // only test that may take them.
+        const W: usize = 16;
+        const H: usize = 20;
+        let mut framebuffer = [0; W * H * 4];
+        let info = FrameBufferInfo {
+            byte_len: framebuffer.len(),
+            width: W,
+            height: H,
+            pixel_format: PixelFormat::Rgb,
+            bytes_per_pixel: 4,
+            stride: W,
+        };
+        // Failing on the format doesn't use one up.
+        let pixel_format = PixelFormat::Unknown { red_position: 30, green_position: 0, blue_position: 8 };
+        assert!(FrameBufferWriter::try_new(&mut framebuffer, FrameBufferInfo { pixel_format, ..info }).is_err());
+        for _ in 0..BUILTIN_TEXT_BUFFERS {
+            let mut writer = FrameBufferWriter::new(&mut framebuffer, info);
+            writer.write_str("a").unwrap();
+            assert_eq!(writer.char_at(0, 0), Some('a'));
+        }
+        let result = FrameBufferWriter::try_new(&mut framebuffer, info).err();
+        assert_eq!(result, Some(WriterError::NoTextBuffer));
+        let mut text = TextBuffer::new();
+        let mut writer = FrameBufferWriter::new_in(&mut framebuffer, info, &mut text);
+        writer.write_str("b").unwrap();
+        assert_eq!(writer.char_at(0, 0), Some('b'));
+    }
+
+    #[cfg(feature = "unchecked")]
+    #[test]
+    #[should_panic(expected = "Framebuffer should hold every pixel.")]
+    fn unchecked_writes_need_the_whole_framebuffer() {
+        let mut fb = mock();
+        let info = FrameBufferInfo { byte_len: WIDTH * HEIGHT * 4 - 1, ..fb.info() };
+        let (bytes, text) = fb.parts();
+        let _ = FrameBufferWriter::try_new_in(&mut bytes[..info.byte_len], info, text);
+    }
+
+    /// The same scene has to come out pixel for pixel the same with and
+    /// without the `unchecked` feature, so this runs under both.
+    #[test]
+    fn fixed_scene_matches_its_golden_checksum() {
+        let checksum = checksum_of(|w| {
+            w.draw_filled_rect(2, 2, 20, 10, [200, 40, 0]);
+            w.draw_line(0, HEIGHT - 1, WIDTH - 1, 0, [0, 120, 255]);
+            w.set_text_color([0, 255, 0]);
+            w.write_str("CA2\nok").unwrap();
+        });
+        assert_eq!(checksum, 0xc68a_da45_6089_fd6b);
+    }
+
+    #[test]
+    fn checksum_and_snapshot_follow_the_pixels() {
+        let blank = checksum_of(|_| {});
+        let a = checksum_of(|w| w.write_str("a").unwrap());
+        assert_ne!(blank, a);
+        assert_eq!(a, checksum_of(|w| w.write_str("a").unwrap()));
+
+        let mut fb = mock();
+        let mut snapshot = [0; WIDTH * HEIGHT * 4];
+        let mut writer = fb.writer();
+        writer.write_str("a").unwrap();
+        writer.snapshot(&mut snapshot);
+        assert_eq!(fb.bytes(), &snapshot[..]);
+    }
+
+    #[test]
+    fn dims_and_brightens() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.fill_screen([200, 100, 50]);
+        writer.dim_screen(255);
+        writer.dim_screen(128);
+        writer.set_brightness(128);
+        writer.set_pixel(0, 0, [255, 255, 255]);
+        assert_eq!(fb.pixel_at(WIDTH - 1, HEIGHT - 1), [100, 50, 25]);
+        assert_eq!(fb.pixel_at(0, 0), [128, 128, 128]);
+    }
+
+    #[test]
+    fn grayscale_keeps_antialiasing() {
+        let raster = get_char_raster('a').unwrap();
+        let mut fb = Mock::new(PixelFormat::U8, WIDTH, HEIGHT);
+        fb.writer().write_str("a").unwrap();
+        for (y, row) in raster.raster().iter().enumerate() {
+            for (x, &intensity) in row.iter().enumerate() {
+                assert_eq!(fb.bytes()[(1 + y) * WIDTH + 1 + x], intensity, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn scrolls_pixels() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_pixel(5, 10, [255, 0, 0]);
+        writer.scroll_pixels_up(3);
+        writer.set_pixel(10, 5, [0, 255, 0]);
+        // This one drops off the left edge rather than ending up in the row
+        // above.
+        writer.set_pixel(1, 6, [0, 0, 255]);
+        writer.scroll_pixels_left(3);
+        assert_eq!(fb.pixel_at(2, 7), [255, 0, 0]);
+        assert_eq!(fb.pixel_at(7, 5), [0, 255, 0]);
+        assert_eq!(lit(&fb, 0, 0, WIDTH, HEIGHT), 2);
+    }
+
+    #[test]
+    fn boxes_are_clipped_to_the_screen() {
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        let corner = if writer.has_glyph('┌') { '┌' } else { '+' };
+        let cursor = writer.cursor();
+        writer.draw_box(0, 0, 3, 3);
+        assert_eq!(writer.cursor(), cursor);
+        assert_eq!(writer.char_at(0, 0), Some(corner));
+        assert_eq!(writer.char_at(1, 1), Some(' '));
+        assert_ne!(writer.char_at(2, 2), Some(' '));
+
+        let (columns, rows) = (writer.columns(), writer.rows());
+        writer.draw_box(columns - 1, rows - 1, 4, 4);
+        assert_eq!(writer.char_at(columns - 1, rows - 1), Some(corner));
+        writer.draw_box(usize::MAX - 1, usize::MAX - 1, 5, 5);
+        writer.draw_box(0, 0, 1, 5);
+    }
+
+    #[test]
+    fn cached_rasters_match_the_font() {
+        for c in [' ', 'a', 'Z', '~', '\u{7f}', 'é', '\u{4e00}'] {
+            let expected = get_raster(c, FONT_WEIGHT, CHAR_RASTER_HEIGHT)
+                .or_else(|| get_raster(BACKUP_CHAR, FONT_WEIGHT, CHAR_RASTER_HEIGHT))
+                .unwrap();
+            assert_eq!(get_char_raster(c).unwrap().raster(), expected.raster(), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn color_setters() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_text_color_u32(0xff8000);
+        assert_eq!(writer.text_color, [255, 128, 0]);
+        writer.set_bg_color([10, 10, 10]);
+        writer.set_contrasting_text_color();
+        assert_eq!(writer.text_color, [255, 255, 255]);
+        writer.set_bg_color([250, 250, 200]);
+        writer.set_contrasting_text_color();
+        assert_eq!(writer.text_color, [0, 0, 0]);
+    }
+
+    #[test]
+    fn default_colors_are_what_resets_go_back_to() {
+        let (green, blue) = ([0, 255, 0], [0, 0, 255]);
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_default_colors(green, blue);
+        assert_eq!((writer.text_color, writer.bg_color), (DEFAULT_TEXT_COLOR, DEFAULT_BG_COLOR));
+        writer.set_text_color([255, 0, 0]);
+        writer.reset_colors();
+        assert_eq!((writer.text_color, writer.bg_color), (green, blue));
+        writer.write_str("\x1b[31;47m\x1b[0m").unwrap();
+        assert_eq!((writer.text_color, writer.bg_color), (green, blue));
+        writer.write_str("\x1b[31;47m\x1b[39m").unwrap();
+        assert_eq!(writer.text_color, green);
+        writer.write_str("\x1b[49m").unwrap();
+        assert_eq!(writer.bg_color, blue);
+    }
+
+    #[test]
+    fn size_modes_stretch_the_cell() {
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        writer.set_size_mode(SizeMode::DoubleWidth);
+        writer.write_str("a").unwrap();
+        assert_eq!(writer.cursor(), (1 + 2 * CHAR_WIDTH, 1));
+
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        writer.set_size_mode(SizeMode::DoubleHeight);
+        writer.write_str("l\n").unwrap();
+        assert_eq!(writer.cursor(), (1, 1 + 2 * CHAR_HEIGHT + LINE_SPACING));
+        assert!(lit(&fb, 1, 1 + CHAR_HEIGHT, CHAR_WIDTH, CHAR_HEIGHT) > 0);
+    }
+
+    #[test]
+    fn inserts_and_deletes_lines() {
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        writer.write_str("a\nb\nc").unwrap();
+        writer.set_bg_color([0, 0, 255]);
+        writer.insert_line(1);
+        assert_eq!([0, 1, 2, 3].map(|row| row_text(&writer, row)), ["a", "", "b", "c"]);
+        writer.delete_line(0);
+        assert_eq!([0, 1, 2, 3].map(|row| row_text(&writer, row)), ["", "b", "c", ""]);
+        // The row opened up and the one left blank at the bottom.
+        assert_eq!(fb.pixel_at(WIDTH - 1, 1), [0, 0, 255]);
+        assert_eq!(fb.pixel_at(WIDTH - 1, 1 + 3 * LINE_HEIGHT), [0, 0, 255]);
+    }
+
+    #[test]
+    fn scroll_region_keeps_the_header() {
+        let mut header = tall_mock();
+        header.writer().write_str("H").unwrap();
+
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        writer.write_str("H").unwrap();
+        writer.set_scroll_region(1, 3);
+        writer.move_to(0, 1);
+        writer.write_str("1\n2\n3\n4\n5").unwrap();
+        assert_eq!([0, 1, 2, 3].map(|row| row_text(&writer, row)), ["H", "3", "4", "5"]);
+        for y in 0..LINE_HEIGHT {
+            for x in 0..WIDTH {
+                assert_eq!(fb.pixel_at(x, y), header.pixel_at(x, y), "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn clears_in_a_color() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("abc").unwrap();
+        writer.clear_color([255, 0, 0]);
+        assert_eq!((writer.cursor(), row_text(&writer, 0).as_str()), ((1, 1), ""));
+        assert!((0..HEIGHT).all(|y| (0..WIDTH).all(|x| fb.pixel_at(x, y) == [255, 0, 0])));
+    }
+
+    #[test]
+    fn bytes_draw_like_strings() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_bytes(b"Hi\n");
+        let (bytes_cursor, bytes_checksum) = (writer.cursor(), writer.checksum());
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("Hi\n").unwrap();
+        assert_eq!((bytes_cursor, bytes_checksum), (writer.cursor(), writer.checksum()));
+
+        writer.write_bytes(&[0xff, b'x']);
+        assert_eq!(row_text(&writer, 1), format!("{}x", BACKUP_CHAR));
+    }
+
+    #[test]
+    fn shadow_and_outline_draw_behind_the_glyph() {
+        const RED: [u8; 3] = [255, 0, 0];
+        for effect in [TextEffect::Shadow(RED, 2, 2), TextEffect::Outline(RED)] {
+            let mut fb = mock();
+            let mut writer = fb.writer();
+            writer.set_text_effect(effect);
+            writer.write_str("l").unwrap();
+            // Wherever the glyph itself doesn't cover it, the effect shows
+            // through in shades of red.
+            let reddish = |[r, g, b]: [u8; 3]| r > 0 && g == 0 && b == 0;
+            let red = (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).filter(|&(x, y)| reddish(fb.pixel_at(x, y)));
+            assert!(red.count() > 0, "{:?}", effect);
+            assert_eq!(fb.pixel_at(1 + CHAR_WIDTH + 3, 1), [0; 3]);
+        }
+    }
+
+    #[test]
+    fn numbers_draw_like_format() {
+        for n in [0, 7, 42, 1_234_567_890, u64::MAX] {
+            assert_eq!(checksum_of(|w| w.write_u64(n)), checksum_of(|w| write!(w, "{}", n).unwrap()), "{}", n);
+            assert_eq!(checksum_of(|w| w.write_hex(n)), checksum_of(|w| write!(w, "{:x}", n).unwrap()), "{}", n);
+            let padded = checksum_of(|w| w.write_u64_padded(n, 5));
+            assert_eq!(padded, checksum_of(|w| write!(w, "{:>5}", n).unwrap()), "{}", n);
+        }
+    }
+
+    #[test]
+    fn colored_writes_restore_the_color() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_colored([255, 0, 0], "a");
+        writer.write_colored_fmt([0, 255, 0], format_args!("{}", 1));
+        writer.write_str("b").unwrap();
+        let fg = [0, 1, 2].map(|col| writer.cells[0][col].fg);
+        assert_eq!(fg, [[255, 0, 0], [0, 255, 0], DEFAULT_TEXT_COLOR]);
+        assert_eq!(writer.text_color, DEFAULT_TEXT_COLOR);
+    }
+
+    #[test]
+    fn without_autowrap_the_rest_of_the_line_is_dropped() {
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.set_autowrap(false);
+        writer.write_str("abcdefghij").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("abcdefgh", ""));
+        assert_eq!(writer.cursor().1, 1);
+        assert_eq!(writer.try_write_char('k'), Err(WriterError::OutOfBounds));
+        writer.write_str("\nx").unwrap();
+        assert_eq!(row_text(&writer, 1), "x");
+    }
+
+    #[test]
+    fn wide_glyphs_wrap_before_the_edge() {
+        let scaled_width = 2 * CHAR_WIDTH;
+        let width = 2 + 3 * scaled_width + 5;
+        let mut fb = TallMock::new(PixelFormat::Rgb, width, 80);
+        let mut writer = fb.writer();
+        writer.set_scale(2);
+        writer.write_str("abcd").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("abc", "d"));
+        assert_eq!(lit(&fb, 1 + 3 * scaled_width, 0, width - 1 - 3 * scaled_width, 80), 0);
+    }
+
+    #[test]
+    fn right_to_left() {
+        let mut fb = MockFrameBuffer::<{ 110 * 60 * 4 }>::new(PixelFormat::Rgb, 110, 60);
+        let mut writer = fb.writer();
+        writer.set_text_direction(TextDirection::Rtl);
+        let start = writer.cursor();
+        assert_eq!(start, (109, 1));
+        writer.write_str("abc").unwrap();
+        let last = writer.screen_columns() - 1;
+        assert_eq!([0, 1, 2].map(|i| writer.char_at(last - i, 0)), [Some('a'), Some('b'), Some('c')]);
+
+        // Backspace goes back to the right and takes the char with it.
+        writer.write_str("\x08\x08\x08").unwrap();
+        assert_eq!(writer.cursor(), start);
+        assert_eq!(row_text(&writer, 0), "");
+        writer.write_str("W").unwrap();
+        assert_eq!(writer.cursor(), (109 - CHAR_WIDTH, 1));
+        writer.write_str("\x08").unwrap();
+        assert_eq!(writer.cursor(), start);
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn override_stride() {
+        const W: usize = 10;
+        const H: usize = 4;
+        const REAL_STRIDE: usize = 12;
+        let info = FrameBufferInfo {
+            byte_len: REAL_STRIDE * H * 4,
+            width: W,
+            height: H,
+            pixel_format: PixelFormat::Rgb,
+            bytes_per_pixel: 4,
+            stride: W,
+        };
+        let mut fb = MockFrameBuffer::<{ REAL_STRIDE * H * 4 }>::with_info(info);
+        let mut writer = fb.writer();
+        assert!(!writer.override_stride(W - 1));
+        assert!(!writer.override_stride(100));
+        assert!(writer.override_stride(REAL_STRIDE));
+        assert_eq!(writer.stride(), REAL_STRIDE);
+        writer.set_pixel(0, 1, [255, 0, 0]);
+        let offset = REAL_STRIDE * 4;
+        assert_eq!(fb.bytes()[offset..offset + 3], [255, 0, 0]);
+    }
+
+    #[test]
+    fn missing_glyphs() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        assert_eq!(writer.try_write_char('\u{4e00}'), Err(WriterError::MissingGlyph('\u{4e00}')));
+        assert_eq!(writer.try_write_char('a'), Ok(()));
+        assert_eq!(writer.cursor(), (1 + CHAR_WIDTH, 1));
+
+        // Written anyway, it takes a cell and shows the backup char.
+        let missing = checksum_of(|w| w.write_str("\u{4e00}").unwrap());
+        assert_eq!(missing, checksum_of(|w| write!(w, "{}", BACKUP_CHAR).unwrap()));
+
+        let Glyph::Solid { width, height } = builtin_glyph(None) else {
+            panic!("no fallback box");
+        };
+        assert_eq!((width, height), (CHAR_WIDTH, CHAR_HEIGHT));
+        assert_eq!(builtin_glyph(None).intensity(0, 0), 255);
+    }
+
+    #[test]
+    fn move_to_clamps_to_the_screen() {
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        writer.move_to(3, 2);
+        assert_eq!(writer.cursor(), (1 + 3 * CHAR_WIDTH, 1 + 2 * LINE_HEIGHT));
+        writer.move_to(999, 999);
+        assert_eq!(writer.cursor(), (1 + 10 * CHAR_WIDTH, 1 + 3 * LINE_HEIGHT));
+        writer.move_to(0, 0);
+        assert_eq!(writer.cursor(), (1, 1));
+    }
+
+    #[test]
+    fn cursor_movement_sequences() {
+        let mut fb = tall_mock();
+        let mut writer = fb.writer();
+        let cell = |writer: &FrameBufferWriter| writer.cursor_position_cell();
+        writer.write_str("\x1b[3B").unwrap();
+        assert_eq!(cell(&writer), (0, 3));
+        writer.write_str("\x1b[A").unwrap();
+        assert_eq!(cell(&writer), (0, 2));
+        writer.write_str("\x1b[4C").unwrap();
+        assert_eq!(cell(&writer), (4, 2));
+        writer.write_str("\x1b[0D").unwrap();
+        assert_eq!(cell(&writer), (3, 2));
+        writer.write_str("\x1b[9A\x1b[99D").unwrap();
+        assert_eq!(cell(&writer), (0, 0));
+        writer.write_str("\x1b[2;5H").unwrap();
+        assert_eq!(cell(&writer), (4, 1));
+        writer.write_str("\x1b[H").unwrap();
+        assert_eq!(cell(&writer), (0, 0));
+    }
+
+    #[test]
+    fn viewport_stays_inside_its_rectangle() {
+        const RED: [u8; 3] = [255, 0, 0];
+        let (x, y, w, h) = (10, 10, 40, 30);
+        let mut fb = MockFrameBuffer::<{ WIDTH * 60 * 4 }>::new(PixelFormat::Rgb, WIDTH, 60);
+        fb.writer().fill_screen(RED);
+        let info = fb.info();
+        let (bytes, text) = fb.parts();
+        let mut writer = FrameBufferWriter::with_viewport_in(bytes, info, x, y, w, h, text);
+        assert_eq!(writer.dimensions(), (w, h));
+        writer.write_str("lots of text\tthat scrolls\nand wraps around\n\x1b[7mreversed\x1b[2J").unwrap();
+        writer.write_str("more\x1b[L\x1b[M").unwrap();
+        writer.scroll_up(1);
+        writer.insert_line(0);
+        writer.delete_line(0);
+        writer.scroll_pixels_up(5);
+        writer.scroll_pixels_left(5);
+        writer.draw_filled_rect(0, 0, usize::MAX, usize::MAX, [0, 255, 0]);
+        writer.draw_line(0, 0, 500, 500, [0, 0, 255]);
+        writer.write_str_at(30, 25, "clipped at the edge");
+        for py in 0..60 {
+            for px in 0..WIDTH {
+                let inside = (x..x + w).contains(&px) && (y..y + h).contains(&py);
+                if !inside {
+                    assert_eq!(fb.pixel_at(px, py), RED, "({}, {}) was drawn to", px, py);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stacked_viewports_share_a_framebuffer() {
+        const RED: [u8; 3] = [255, 0, 0];
+        const BLUE: [u8; 3] = [0, 0, 255];
+        let split = HEIGHT / 2;
+        let mut fb = mock();
+        let info = fb.info();
+        let mut second_text = TextBuffer::new();
+        let (bytes, text) = fb.parts();
+        let (top, bottom) = bytes.split_at_mut(split * info.stride * info.bytes_per_pixel);
+        let bottom_info = FrameBufferInfo { height: HEIGHT - split, byte_len: bottom.len(), ..info };
+        let mut upper = FrameBufferWriter::with_viewport_in(top, info, 0, 0, WIDTH, split, text);
+        let mut lower = FrameBufferWriter::with_viewport_in(bottom, bottom_info, 0, 0, WIDTH, split, &mut second_text);
+        upper.set_bg_color(RED);
+        lower.set_bg_color(BLUE);
+        upper.clear();
+        lower.clear();
+        for _ in 0..5 {
+            upper.write_str("upper line\n").unwrap();
+            lower.write_str("lower line\n").unwrap();
+        }
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let foreign = if y < split { BLUE } else { RED };
+                assert_ne!(fb.pixel_at(x, y), foreign, "({}, {})", x, y);
+            }
+        }
+        assert_eq!((fb.pixel_at(0, 0), fb.pixel_at(0, HEIGHT - 1)), (RED, BLUE));
+    }
+
+    #[test]
+    fn full_lines_wrap_on_the_next_char() {
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.write_str("abcdefgh").unwrap();
+        assert_eq!(writer.cursor(), (1 + 7 * CHAR_WIDTH, 1));
+        assert_eq!(row_text(&writer, 1), "");
+        writer.write_str("i").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("abcdefgh", "i"));
+
+        // A newline right after a full line doesn't leave an empty one.
+        let mut fb = narrow_mock(8);
+        let mut writer = fb.writer();
+        writer.write_str("abcdefgh\nx").unwrap();
+        assert_eq!(row_text(&writer, 1), "x");
+    }
+
+    #[test]
+    fn combining_marks_take_no_cell() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("e\u{301}x\u{4e00}").unwrap();
+        assert_eq!(row_text(&writer, 0), "ex\u{4e00}");
+        assert_eq!(writer.cursor(), (1 + 3 * CHAR_WIDTH, 1));
+    }
+
+    #[test]
+    fn text_stays_within_rows() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("a\nb").unwrap();
+        assert_eq!(row_text(&writer, 0), "a");
+        writer.write_str("\nc").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("b", "c"));
+        writer.write_str(&"\n".repeat(10)).unwrap();
+        assert!(writer.cursor().1 <= 1 + writer.rows() * LINE_HEIGHT);
+        writer.write_str("d").unwrap();
+        assert!(lit(&fb, 1, HEIGHT - 1 - CHAR_HEIGHT - LINE_SPACING, CHAR_WIDTH, CHAR_HEIGHT + LINE_SPACING) > 0);
+    }
+
+    #[test]
+    fn monospace_advance() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_monospace_advance(Some(10));
+        writer.write_str("i").unwrap();
+        assert_eq!(writer.cursor(), (11, 1));
+        writer.write_str("W").unwrap();
+        assert_eq!(writer.cursor(), (21, 1));
+        writer.set_monospace_advance(Some(0));
+        writer.write_str("i").unwrap();
+        assert_eq!(writer.cursor(), (21 + CHAR_WIDTH, 1));
     }
 }
@@ -0,0 +1,207 @@
+use super::FrameBufferWriter;
+
+/// Size of the file header plus the smallest info header we accept
+/// (BITMAPINFOHEADER).
+const HEADER_LEN: usize = 14 + 40;
+
+/// Why a BMP image couldn't be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BmpError {
+    /// The file doesn't start with `BM`.
+    BadMagic,
+    /// The file ends before the headers or the pixel rows do.
+    Truncated,
+    /// The info header is an old or unknown variant.
+    UnsupportedHeader,
+    /// Only 24 bits per pixel are supported.
+    UnsupportedBitDepth(u16),
+    /// Only uncompressed (BI_RGB) images are supported.
+    Compressed,
+    /// Rows stored top to bottom (negative height) aren't supported.
+    TopDown,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// Bytes per pixel row in the file; rows are padded to a multiple of 4.
+fn row_len(width: usize) -> usize {
+    (width * 3).div_ceil(4) * 4
+}
+
+/// Parses an uncompressed 24-bit BMP file. Returns its width, height and
+/// pixel rows: bottom row first, BGR bytes, each row padded to 4 bytes.
+pub fn decode_bmp(bytes: &[u8]) -> Result<(usize, usize, &[u8]), BmpError> {
+    if bytes.len() < 2 || &bytes[..2] != b"BM" {
+        return Err(BmpError::BadMagic);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(BmpError::Truncated);
+    }
+    if read_u32(bytes, 14) < 40 {
+        return Err(BmpError::UnsupportedHeader);
+    }
+    let bits_per_pixel = read_u16(bytes, 28);
+    if bits_per_pixel != 24 {
+        return Err(BmpError::UnsupportedBitDepth(bits_per_pixel));
+    }
+    if read_u32(bytes, 30) != 0 {
+        return Err(BmpError::Compressed);
+    }
+    let width = read_u32(bytes, 18) as i32;
+    let height = read_u32(bytes, 22) as i32;
+    if height < 0 {
+        return Err(BmpError::TopDown);
+    }
+    if width < 0 {
+        return Err(BmpError::UnsupportedHeader);
+    }
+    let (width, height) = (width as usize, height as usize);
+    let start = read_u32(bytes, 10) as usize;
+    let end = row_len(width)
+        .checked_mul(height)
+        .and_then(|len| len.checked_add(start))
+        .ok_or(BmpError::Truncated)?;
+    let pixels = bytes.get(start..end).ok_or(BmpError::Truncated)?;
+    Ok((width, height, pixels))
+}
+
+/// How many pixels of a row [FrameBufferWriter::draw_bmp] converts at once.
+const ROW_CHUNK: usize = 64;
+
+impl FrameBufferWriter<'_> {
+    /// Decodes a BMP image (see [decode_bmp]) and draws it with its top left
+    /// corner at (x, y), clipped like [Self::blit]. Draws nothing if the
+    /// image can't be decoded.
+    pub fn draw_bmp(&mut self, x: usize, y: usize, bytes: &[u8]) -> Result<(), BmpError> {
+        let (width, _, pixels) = decode_bmp(bytes)?;
+        if width == 0 {
+            return Ok(());
+        }
+        // The file stores the bottom row first, in BGR order. Each row is
+        // turned into RGB a piece at a time and drawn like a row of blit.
+        let mut rgb = [[0; 3]; ROW_CHUNK];
+        for (row, row_bytes) in pixels.chunks_exact(row_len(width)).rev().enumerate() {
+            let row_bytes = &row_bytes[..width * 3];
+            for (chunk, bgr) in row_bytes.chunks(ROW_CHUNK * 3).enumerate() {
+                let len = bgr.len() / 3;
+                for (dst, src) in rgb.iter_mut().zip(bgr.chunks_exact(3)) {
+                    *dst = [src[2], src[1], src[0]];
+                }
+                let col = x.saturating_add(chunk * ROW_CHUNK);
+                self.blit_row(col, y.saturating_add(row), &rgb[..len]);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::{decode_bmp, BmpError};
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const GREEN: [u8; 3] = [0, 255, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+    const WHITE: [u8; 3] = [255, 255, 255];
+
+    /// A 2x2 BMP: red and white on top, blue and green at the bottom.
+    fn bmp() -> Vec<u8> {
+        let mut bytes = Vec::from(*b"BM");
+        for field in [70, 0, 54, 40, 2, 2] {
+            bytes.extend(u32::to_le_bytes(field));
+        }
+        bytes.extend(u16::to_le_bytes(1));
+        bytes.extend(u16::to_le_bytes(24));
+        bytes.extend([0; 24]);
+        // Bottom row first, BGR, padded to 8 bytes
+        for row in [[BLUE, GREEN], [RED, WHITE]] {
+            for [r, g, b] in row {
+                bytes.extend([b, g, r]);
+            }
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_24_bit_images() {
+        let bytes = bmp();
+        let (width, height, pixels) = decode_bmp(&bytes).unwrap();
+        assert_eq!((width, height, pixels.len()), (2, 2, 16));
+    }
+
+    #[test]
+    fn draws_the_top_row_first() {
+        let bytes = bmp();
+        let mut fb = MockFrameBuffer::<{ 80 * 40 * 4 }>::new(PixelFormat::Rgb, 80, 40);
+        let mut writer = fb.writer();
+        assert_eq!(writer.draw_bmp(10, 20, &bytes), Ok(()));
+        writer.draw_bmp(79, 39, &bytes).unwrap();
+        let colors = [(10, 20), (11, 20), (10, 21), (11, 21), (79, 39)].map(|(x, y)| fb.pixel_at(x, y));
+        assert_eq!(colors, [RED, WHITE, BLUE, GREEN, RED]);
+    }
+
+    #[test]
+    fn draws_like_blit() {
+        // Wider than one converted piece of a row, and hanging off the
+        // right and bottom edges.
+        let (width, height) = (70, 3);
+        let pixels: Vec<[u8; 3]> = (0..width * height).map(|i| [i as u8, (i * 3) as u8, 200]).collect();
+        let mut bytes = Vec::from(*b"BM");
+        for field in [0, 0, 54, 40, width as u32, height as u32] {
+            bytes.extend(u32::to_le_bytes(field));
+        }
+        bytes.extend(u16::to_le_bytes(1));
+        bytes.extend(u16::to_le_bytes(24));
+        bytes.extend([0; 24]);
+        for row in pixels.chunks_exact(width).rev() {
+            for &[r, g, b] in row {
+                bytes.extend([b, g, r]);
+            }
+            bytes.extend([0, 0]);
+        }
+
+        let mut bmp_fb = MockFrameBuffer::<{ 80 * 40 * 4 }>::new(PixelFormat::Rgb, 80, 40);
+        let mut blit_fb = MockFrameBuffer::<{ 80 * 40 * 4 }>::new(PixelFormat::Rgb, 80, 40);
+        let mut bmp_writer = bmp_fb.writer();
+        let mut blit_writer = blit_fb.writer();
+        bmp_writer.flush_damage(&mut [0; 80 * 40 * 4]);
+        blit_writer.flush_damage(&mut [0; 80 * 40 * 4]);
+        bmp_writer.draw_bmp(30, 38, &bytes).unwrap();
+        blit_writer.blit(30, 38, width, height, &pixels).unwrap();
+        assert_eq!(bmp_writer.damage(), Some((30, 38, 80, 40)));
+        assert_eq!(bmp_writer.damage(), blit_writer.damage());
+        assert_eq!(bmp_fb.bytes(), blit_fb.bytes());
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        let bytes = bmp();
+        let with = |offset: usize, value: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+            decode_bmp(&bytes).map(|_| ()).err()
+        };
+        assert_eq!(decode_bmp(b"PNG").err(), Some(BmpError::BadMagic));
+        assert_eq!(decode_bmp(&bytes[..40]).err(), Some(BmpError::Truncated));
+        assert_eq!(decode_bmp(&bytes[..bytes.len() - 1]).err(), Some(BmpError::Truncated));
+        assert_eq!(with(14, &[12, 0, 0, 0]), Some(BmpError::UnsupportedHeader));
+        assert_eq!(with(28, &[32, 0]), Some(BmpError::UnsupportedBitDepth(32)));
+        assert_eq!(with(30, &[1, 0, 0, 0]), Some(BmpError::Compressed));
+        assert_eq!(with(22, &(-2i32).to_le_bytes()), Some(BmpError::TopDown));
+        assert_eq!(with(10, &u32::MAX.to_le_bytes()), Some(BmpError::Truncated));
+    }
+}
@@ -0,0 +1,734 @@
+use spin::{Mutex, MutexGuard};
+
+use super::scrollback::Scrollback;
+use super::{FrameBufferWriter, TextAttrs, WriterError, DEFAULT_BG_COLOR, DEFAULT_TEXT_COLOR};
+
+/// Most char columns per text row that are remembered for redrawing.
+/// Columns further right are drawn but not remembered.
+pub const MAX_COLUMNS: usize = 160;
+
+/// Most text rows on screen that are remembered for redrawing.
+pub const MAX_ROWS: usize = 64;
+
+/// Bytes of scanlines [FrameBufferWriter::write_line] can stage a row in:
+/// enough for a default size text row on a 1920 pixel wide 32-bit screen.
+pub const LINE_STAGING_LEN: usize = 20 * 1920 * 4;
+
+/// How many writers can be created without lending them a [TextBuffer].
+pub const BUILTIN_TEXT_BUFFERS: usize = 2;
+
+/// The text buffers handed out to writers created without one, see
+/// [FrameBufferWriter::new].
+static BUILTIN_TEXT: [Mutex<TextBuffer>; BUILTIN_TEXT_BUFFERS] =
+    [const { Mutex::new(TextBuffer::new()) }; BUILTIN_TEXT_BUFFERS];
+
+/// A char as it was written to the screen, with its colors and decorations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: [u8; 3],
+    pub bg: [u8; 3],
+    pub attrs: TextAttrs,
+}
+
+impl Cell {
+    /// An empty cell in the default colors.
+    pub const BLANK: Cell = Cell {
+        ch: ' ',
+        fg: DEFAULT_TEXT_COLOR,
+        bg: DEFAULT_BG_COLOR,
+        attrs: TextAttrs::empty(),
+    };
+}
+
+/// One text row worth of cells.
+pub type Row = [Cell; MAX_COLUMNS];
+
+/// Where [FrameBufferWriter::find] found a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextPosition {
+    /// Cell (col, row) of the screen.
+    Screen { col: usize, row: usize },
+    /// Column `col` of the row `above` rows above the top of the screen, 1
+    /// being the row right above it. Scrolled back by `above` rows with
+    /// [FrameBufferWriter::scroll_back], it is at the top of the screen.
+    Scrollback { col: usize, above: usize },
+}
+
+/// What a writer remembers of its text: the chars on the screen and the
+/// [super::SCROLLBACK_LINES] rows above them, plus the scanlines
+/// [FrameBufferWriter::write_line] stages a row in. At around 400 KB it
+/// doesn't belong on the stack: the writer constructors take one of
+/// [BUILTIN_TEXT_BUFFERS] statics, or one kept e.g. in a `static` of your
+/// own can be lent to [FrameBufferWriter::new_in].
+pub struct TextBuffer {
+    pub(super) cells: [Row; MAX_ROWS],
+    pub(super) scrollback: Scrollback,
+    pub(super) line_staging: [u8; LINE_STAGING_LEN],
+}
+
+impl TextBuffer {
+    pub const fn new() -> Self {
+        Self {
+            cells: [[Cell::BLANK; MAX_COLUMNS]; MAX_ROWS],
+            scrollback: Scrollback::new(),
+            line_staging: [0; LINE_STAGING_LEN],
+        }
+    }
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The columns where `needle` starts within the first `columns` cells of
+/// `row`.
+fn matches_in<'s>(row: &'s Row, columns: usize, needle: &'s str) -> impl Iterator<Item = usize> + 's {
+    let len = needle.chars().count();
+    (0..(columns + 1).saturating_sub(len))
+        .filter(move |&col| len > 0 && row[col..col + len].iter().map(|cell| cell.ch).eq(needle.chars()))
+}
+
+/// Takes a free buffer out of [BUILTIN_TEXT] for good, if there is one left.
+pub(super) fn claim_builtin_text() -> Option<&'static mut TextBuffer> {
+    BUILTIN_TEXT.iter().find_map(|text| text.try_lock()).map(MutexGuard::leak)
+}
+
+/// Keeping track of which char is in which cell of the screen, so the text
+/// can be drawn again without losing its colors.
+impl FrameBufferWriter<'_> {
+    /// Redraws all text on the screen from the cell grid. Anything else drawn
+    /// on the screen, e.g. with [Self::draw_line], is painted over.
+    pub fn repaint(&mut self) {
+        let line_height = self.line_height();
+        for row in 0..self.screen_rows() {
+            let y = self.text_top() + row * line_height;
+            let (x, _, width, _) = self.text_area();
+            self.draw_filled_rect(x, y, width, line_height, self.bg_color);
+            let cells = self.cells[row];
+            self.draw_row(y, &cells);
+        }
+        if let Some((start, end)) = self.highlight {
+            self.draw_cell_range(start, end, true);
+        }
+    }
+
+    /// Highlights the cells from `start` to `end`, both (col, row) and
+    /// included, by drawing them with their text and background colors
+    /// swapped. Like a text selection the range runs to the end of `start`'s
+    /// row and on from the start of the next one, and the two ends can come
+    /// in either order. Replaces any previous highlight. The range is in
+    /// screen cells, so it stays put when the text scrolls.
+    pub fn highlight_cells(&mut self, start: (usize, usize), end: (usize, usize)) {
+        self.clear_highlight();
+        let (start, end) = if (start.1, start.0) <= (end.1, end.0) { (start, end) } else { (end, start) };
+        self.highlight = Some((start, end));
+        self.draw_cell_range(start, end, true);
+    }
+
+    /// Draws the highlighted cells in their own colors again.
+    pub fn clear_highlight(&mut self) {
+        if let Some((start, end)) = self.highlight.take() {
+            self.draw_cell_range(start, end, false);
+        }
+    }
+
+    /// The char in cell (col, row) of the screen, a space for an empty cell.
+    /// Returns None for cells that are off screen or not kept in the grid.
+    pub fn char_at(&self, col: usize, row: usize) -> Option<char> {
+        if row >= self.screen_rows() {
+            return None;
+        }
+        self.cells[row].get(col).map(|cell| cell.ch)
+    }
+
+    /// Where `needle` first appears in the text, going through the rows that
+    /// scrolled off the top of the screen into the scrollback and then the
+    /// screen, top to bottom and each row left to right. Matches don't run
+    /// across rows; empty cells match spaces.
+    pub fn find(&self, needle: &str) -> Option<TextPosition> {
+        self.find_all(needle).next()
+    }
+
+    /// Like [Self::find], but goes through every match. Matches may overlap.
+    pub fn find_all<'s>(&'s self, needle: &'s str) -> impl Iterator<Item = TextPosition> + 's {
+        let columns = self.screen_columns();
+        // The newest rows of the scrollback are the ones above the cursor,
+        // which are still on screen, like in Self::scroll_back.
+        let (_, cursor_row) = self.cursor_cell();
+        let total = self.scrollback.total();
+        let first_on_screen = total.saturating_sub(cursor_row);
+        let history = (total - self.scrollback.len()..first_on_screen)
+            .filter_map(move |index| Some((first_on_screen - index, self.scrollback.row(index)?)))
+            .flat_map(move |(above, row)| {
+                matches_in(row, columns, needle).map(move |col| TextPosition::Scrollback { col, above })
+            });
+        let screen = (0..self.screen_rows()).flat_map(move |row| {
+            matches_in(&self.cells[row], columns, needle).map(move |col| TextPosition::Screen { col, row })
+        });
+        history.chain(screen)
+    }
+
+    /// Blanks cell (col, row) to the background color without moving the
+    /// streaming cursor. Cells that are off screen are ignored.
+    pub fn clear_cell(&mut self, col: usize, row: usize) {
+        if col >= self.screen_columns() || row >= self.screen_rows() {
+            return;
+        }
+        let (x, y) = self.cell_origin(col, row);
+        let cell_width = self.cell_width();
+        self.draw_filled_rect(x, y, cell_width, self.char_height(), self.bg_color);
+        self.cells[row][col] = Cell::BLANK;
+    }
+
+    /// Replaces the char in cell (col, row) with `c`, in the current colors
+    /// and decorations, without moving the streaming cursor. Meant for
+    /// updating a single char, e.g. a spinner, without redrawing the line.
+    pub fn write_char_at(&mut self, col: usize, row: usize, c: char) {
+        if col >= self.screen_columns() || row >= self.screen_rows() {
+            return;
+        }
+        self.clear_cell(col, row);
+        let (saved_x, saved_y) = (self.x_pos, self.y_pos);
+        (self.x_pos, self.y_pos) = self.cell_origin(col, row);
+        self.record_cell(c);
+        self.draw_char(c);
+        (self.x_pos, self.y_pos) = (saved_x, saved_y);
+    }
+
+    /// Shows `s` in the `max_width_cells` cells starting at cell (x, y),
+    /// redrawing only the cells whose char or colors change, e.g. for a
+    /// counter updated many times a second. Cells past the end of `s` are
+    /// blanked, and chars past the field are dropped. Doesn't move the
+    /// streaming cursor.
+    pub fn update_field(&mut self, x: usize, y: usize, max_width_cells: usize, s: &str) {
+        if y >= self.screen_rows() {
+            return;
+        }
+        let (fg, bg) = self.effective_colors();
+        let end = x.saturating_add(max_width_cells).min(self.screen_columns());
+        let mut chars = s.chars();
+        for col in x..end {
+            let current = self.cells[y][col];
+            match chars.next() {
+                Some(c) => {
+                    if current != (Cell { ch: c, fg, bg, attrs: self.text_attrs }) {
+                        self.write_char_at(col, y, c);
+                    }
+                }
+                None => {
+                    if current != Cell::BLANK {
+                        self.clear_cell(col, y);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replaces text row `row` with `s` in `fg` on `bg`, padding the rest of
+    /// the row with `bg`, without moving the streaming cursor or changing the
+    /// colors. Meant for a status line redrawn from an interrupt handler.
+    ///
+    /// The framebuffer scanlines the row covers are copied to a staging area
+    /// of the [TextBuffer], the row is drawn there, and the scanlines are
+    /// copied back in one go, so the screen never shows the row half drawn.
+    /// With a back buffer the row is drawn into it as well, so the next
+    /// [Self::present] keeps it.
+    ///
+    /// A row whose scanlines take more than [LINE_STAGING_LEN] bytes, e.g. on
+    /// a very wide or a sideways screen, is staged in the back buffer
+    /// instead, and only the row's own pixels are copied from there; whatever
+    /// else is waiting in the back buffer stays there until the next
+    /// [Self::present]. Fails with [WriterError::NoBackBuffer] if there is no
+    /// back buffer then, and with [WriterError::OutOfBounds] if `row` is off
+    /// the screen. Nothing is drawn then.
+    pub fn write_line(&mut self, row: usize, s: &str, fg: [u8; 3], bg: [u8; 3]) -> Result<(), WriterError> {
+        if row >= self.screen_rows() {
+            return Err(WriterError::OutOfBounds);
+        }
+        let (_, y) = self.cell_origin(0, row);
+        // Rotation keeps a rectangle a rectangle, so two opposite corners
+        // give the scanlines the whole row is on.
+        let (_, py0) = self.physical(0, y);
+        let (_, py1) = self.physical(self.width().saturating_sub(1), y + self.line_height() - 1);
+        let row_len = self.info.stride * self.info.bytes_per_pixel;
+        let start = (py0.min(py1) * row_len).min(self.framebuffer.len());
+        let end = ((py0.max(py1) + 1) * row_len).min(self.framebuffer.len());
+        let staged = end - start <= LINE_STAGING_LEN;
+        if !staged && self.back_buffer.is_none() {
+            return Err(WriterError::NoBackBuffer);
+        }
+        if staged {
+            self.line_staging[..end - start].copy_from_slice(&self.framebuffer[start..end]);
+            self.staged = Some((start, end));
+            self.render_row(row, s, fg, bg);
+            self.staged = None;
+            self.framebuffer[start..end].copy_from_slice(&self.line_staging[..end - start]);
+        }
+        if self.back_buffer.is_some() {
+            self.render_row(row, s, fg, bg);
+            if !staged {
+                let (left, right) = (self.text_left(), self.text_right());
+                self.present_region(left, y, right.saturating_sub(left), self.line_height());
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the row [Self::write_line] writes.
+    fn render_row(&mut self, row: usize, s: &str, fg: [u8; 3], bg: [u8; 3]) {
+        let colors = (self.text_color, self.bg_color);
+        (self.text_color, self.bg_color) = (fg, bg);
+        let (_, y) = self.cell_origin(0, row);
+        let (left, right) = (self.text_left(), self.text_right());
+        self.draw_filled_rect(left, y, right.saturating_sub(left), self.line_height(), bg);
+        let mut chars = s.chars();
+        for col in 0..self.screen_columns() {
+            self.write_char_at(col, row, chars.next().unwrap_or(' '));
+        }
+        (self.text_color, self.bg_color) = colors;
+    }
+
+    /// Number of text rows that fit on the screen, as far as they are kept in
+    /// the cell grid.
+    pub(super) fn screen_rows(&self) -> usize {
+        let rows = self.text_bottom().saturating_sub(self.text_top()) / self.line_height();
+        rows.min(MAX_ROWS)
+    }
+
+    /// Number of char columns that fit on the screen, as far as they are kept
+    /// in the cell grid.
+    pub(super) fn screen_columns(&self) -> usize {
+        let cell_width = self.cell_width();
+        let columns = self.text_right().saturating_sub(self.text_left()) / cell_width;
+        columns.min(MAX_COLUMNS)
+    }
+
+    /// The pixel position of the top left corner of cell (column, row).
+    pub(super) fn cell_origin(&self, column: usize, row: usize) -> (usize, usize) {
+        (
+            self.text_left() + column * self.cell_width(),
+            self.text_top() + row * self.line_height(),
+        )
+    }
+
+    /// The (column, row) of the cell at the current write position.
+    pub(super) fn cursor_cell(&self) -> (usize, usize) {
+        let cell_width = self.cell_width();
+        (
+            self.x_pos.saturating_sub(self.text_left()) / cell_width,
+            self.y_pos.saturating_sub(self.text_top()) / self.line_height(),
+        )
+    }
+
+    /// Remembers that `c` was written at the current position.
+    pub(super) fn record_cell(&mut self, c: char) {
+        let (column, row) = self.cursor_cell();
+        if column < MAX_COLUMNS && row < MAX_ROWS {
+            let (fg, bg) = self.effective_colors();
+            self.cells[row][column] = Cell { ch: c, fg, bg, attrs: self.text_attrs };
+        }
+    }
+
+    /// Forgets the cells of `row` from column `start` up to, but excluding, `end`.
+    pub(super) fn blank_cells(&mut self, row: usize, start: usize, end: usize) {
+        if let Some(cells) = self.cells.get_mut(row) {
+            let end = end.min(MAX_COLUMNS);
+            if start < end {
+                cells[start..end].fill(Cell::BLANK);
+            }
+        }
+    }
+
+    /// Moves the cell grid up by `lines` rows, the same way
+    /// [Self::scroll_up] moves the pixels.
+    pub(super) fn scroll_cells(&mut self, lines: usize) {
+        if lines >= MAX_ROWS {
+            self.cells.fill([Cell::BLANK; MAX_COLUMNS]);
+            return;
+        }
+        self.cells.copy_within(lines.., 0);
+        self.cells[MAX_ROWS - lines..].fill([Cell::BLANK; MAX_COLUMNS]);
+    }
+
+    /// Lays the rows of the cell grid, up to the one with `cursor` in it, out
+    /// again for the current screen size. Rows wider than the screen wrap,
+    /// and rows that no longer fit drop off the top. Moves the write
+    /// position to where `cursor` ends up.
+    pub(super) fn reflow(&mut self, (cursor_column, cursor_row): (usize, usize)) {
+        let columns = self.screen_columns().max(1);
+        let rows = self.screen_rows().max(1);
+        let cursor_row = cursor_row.min(MAX_ROWS - 1);
+        // An empty row still takes up a row.
+        let chunks_of = |used: usize| used.div_ceil(columns).max(1);
+
+        // The cells in use of each old row, and how many rows that makes.
+        let mut used = [0; MAX_ROWS];
+        let mut total = 0;
+        for (i, old_row) in self.cells.iter().enumerate().take(cursor_row + 1) {
+            used[i] = old_row.iter().rposition(|cell| *cell != Cell::BLANK).map_or(0, |last| last + 1);
+            if i == cursor_row {
+                used[i] = used[i].max(cursor_column.min(MAX_COLUMNS));
+            }
+            total += chunks_of(used[i]);
+        }
+
+        // Drop what won't fit off the top first. The rows left then only
+        // ever move down, so they can be spread out in place, bottom first.
+        let (mut first, mut dropped) = (0, total.saturating_sub(rows));
+        while first < cursor_row && chunks_of(used[first]) <= dropped {
+            dropped -= chunks_of(used[first]);
+            first += 1;
+        }
+        let mut cursor_column = cursor_column;
+        if dropped > 0 {
+            let cut = (dropped * columns).min(used[first]);
+            self.cells[first].copy_within(cut..used[first], 0);
+            self.cells[first][used[first] - cut..].fill(Cell::BLANK);
+            used[first] -= cut;
+            if first == cursor_row {
+                cursor_column = cursor_column.saturating_sub(cut);
+            }
+        }
+        self.cells.copy_within(first..cursor_row + 1, 0);
+        used.copy_within(first..cursor_row + 1, 0);
+        let last = cursor_row - first;
+
+        let row: usize = used[..=last].iter().map(|&used| chunks_of(used)).sum();
+        let mut target = row;
+        for i in (0..=last).rev() {
+            for chunk in (0..chunks_of(used[i])).rev() {
+                target -= 1;
+                let start = (chunk * columns).min(used[i]);
+                let end = (start + columns).min(used[i]);
+                if target > i {
+                    let (old_rows, new_rows) = self.cells.split_at_mut(target);
+                    new_rows[0][..end - start].copy_from_slice(&old_rows[i][start..end]);
+                }
+                // Otherwise this is the row's first chunk, already in place.
+                self.cells[target][end - start..].fill(Cell::BLANK);
+            }
+        }
+        self.cells[row..].fill([Cell::BLANK; MAX_COLUMNS]);
+        let chunks = chunks_of(used[last]);
+
+        let cursor_chunk = (cursor_column / columns).min(chunks - 1);
+        let cell_width = self.cell_width();
+        self.wrap_pending = false;
+        self.x_pos = self.text_left() + (cursor_column - cursor_chunk * columns) * cell_width;
+        self.y_pos = self.text_top() + (row + cursor_chunk).saturating_sub(chunks) * self.line_height();
+    }
+
+    /// Advances the blink cycle: hides the chars written with
+    /// [TextAttrs::BLINK] if they are shown and shows them again if they are
+    /// hidden. Meant to be called from a timer interrupt, e.g. every half
+    /// second.
+    pub fn tick(&mut self) {
+        self.blink_visible = !self.blink_visible;
+        let cell_width = self.cell_width();
+        for row in 0..self.screen_rows() {
+            for column in 0..self.screen_columns() {
+                let cell = self.cells[row][column];
+                if !cell.attrs.contains(TextAttrs::BLINK) {
+                    continue;
+                }
+                if self.blink_visible {
+                    self.draw_cell(column, row, false);
+                } else {
+                    let (x, y) = self.cell_origin(column, row);
+                    self.draw_filled_rect(x, y, cell_width, self.char_height(), cell.bg);
+                }
+            }
+        }
+    }
+
+    /// Draws every on-screen cell from `start` to `end`, wrapping across rows
+    /// like [Self::highlight_cells].
+    fn draw_cell_range(&mut self, start: (usize, usize), end: (usize, usize), highlighted: bool) {
+        let columns = self.screen_columns();
+        for row in start.1..self.screen_rows().min(end.1 + 1) {
+            let first = if row == start.1 { start.0 } else { 0 };
+            let last = if row == end.1 { end.0 } else { columns };
+            for column in first..columns.min(last + 1) {
+                self.draw_cell(column, row, highlighted);
+            }
+        }
+    }
+
+    /// Draws cell (column, row) with its background, swapping its colors if
+    /// `highlighted`.
+    pub(super) fn draw_cell(&mut self, column: usize, row: usize, highlighted: bool) {
+        let cell = self.cells[row][column];
+        let saved = (self.x_pos, self.y_pos, self.text_color, self.bg_color, self.text_attrs);
+        let (saved_opaque, saved_reverse) = (self.opaque_background, self.reverse);
+        (self.x_pos, self.y_pos) = self.cell_origin(column, row);
+        (self.text_color, self.bg_color) = if highlighted { (cell.bg, cell.fg) } else { (cell.fg, cell.bg) };
+        self.text_attrs = cell.attrs;
+        self.opaque_background = true;
+        self.reverse = false;
+        self.draw_char(cell.ch);
+        (self.x_pos, self.y_pos, self.text_color, self.bg_color, self.text_attrs) = saved;
+        (self.opaque_background, self.reverse) = (saved_opaque, saved_reverse);
+    }
+
+    /// Draws the non-blank cells of a text row whose top is at pixel row `y`.
+    pub(super) fn draw_row(&mut self, y: usize, row: &Row) {
+        let saved = (self.x_pos, self.y_pos, self.text_color, self.bg_color);
+        let (saved_attrs, saved_reverse) = (self.text_attrs, self.reverse);
+        self.reverse = false;
+        let cell_width = self.cell_width();
+        for (column, cell) in row.iter().enumerate() {
+            if *cell == Cell::BLANK {
+                continue;
+            }
+            self.x_pos = self.text_left() + column * cell_width;
+            self.y_pos = y;
+            self.text_color = cell.fg;
+            self.bg_color = cell.bg;
+            self.text_attrs = cell.attrs;
+            self.draw_char(cell.ch);
+        }
+        (self.x_pos, self.y_pos, self.text_color, self.bg_color) = saved;
+        (self.text_attrs, self.reverse) = (saved_attrs, saved_reverse);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::fmt::Write;
+    use std::{string::String, vec};
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::{get_char_raster, FrameBufferWriter, TextAttrs, WriterError};
+    use super::{TextPosition, LINE_STAGING_LEN};
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+
+    type Mock = MockFrameBuffer<{ WIDTH * HEIGHT * 4 }>;
+
+    fn mock() -> Mock {
+        Mock::new(PixelFormat::Rgb, WIDTH, HEIGHT)
+    }
+
+    fn row_text(writer: &FrameBufferWriter, row: usize) -> String {
+        let text: String = (0..writer.screen_columns()).filter_map(|col| writer.char_at(col, row)).collect();
+        String::from(text.trim_end())
+    }
+
+    #[test]
+    fn repaint_restores_the_text() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("\x1b[31mab\x1b[0m\ncd").unwrap();
+        let expected = writer.checksum();
+        // Only the text rows are repainted, so scribble inside them.
+        writer.draw_filled_rect(1, 1, WIDTH - 1, 2 * 18, [0, 0, 255]);
+        writer.draw_line(1, 1, 79, 36, [0, 255, 0]);
+        writer.repaint();
+        assert_eq!(writer.checksum(), expected);
+
+        writer.write_str("\x1b[2J").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("", ""));
+        writer.repaint();
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn char_at_reads_the_grid() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("ab\ncd").unwrap();
+        assert_eq!([writer.char_at(0, 0), writer.char_at(1, 1), writer.char_at(5, 0)], [Some('a'), Some('d'), Some(' ')]);
+        assert_eq!(writer.char_at(0, 2), None);
+        assert_eq!(writer.char_at(usize::MAX, 0), None);
+    }
+
+    #[test]
+    fn reinit_reflows_the_text() {
+        let narrow = 1 + 5 * 7;
+        let mut second = [0; 36 * HEIGHT * 4];
+        let info = bootloader_api::info::FrameBufferInfo { width: narrow, stride: narrow, byte_len: second.len(), ..mock().info() };
+        let mut fb = MockFrameBuffer::<{ 71 * HEIGHT * 4 }>::new(PixelFormat::Rgb, 71, HEIGHT);
+        let mut writer = fb.writer();
+        writer.write_str("abcdefghij").unwrap();
+        assert_eq!(row_text(&writer, 0), "abcdefghij");
+        writer.reinit(&mut second, info);
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("abcde", "fghij"));
+        // The wrap that was pending is still pending.
+        writer.write_str("k").unwrap();
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("fghij", "k"));
+    }
+
+    #[test]
+    fn highlight_swaps_the_colors() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("ab").unwrap();
+        let expected = writer.checksum();
+        writer.highlight_cells((1, 0), (0, 0));
+        assert_ne!(writer.checksum(), expected);
+        writer.clear_highlight();
+        assert_eq!(writer.checksum(), expected);
+
+        writer.highlight_cells((0, 0), (0, 0));
+        // The background turns white where the glyph isn't.
+        let raster = get_char_raster('a').unwrap();
+        let (x, y) = (0..raster.height())
+            .flat_map(|y| (0..raster.width()).map(move |x| (x, y)))
+            .find(|&(x, y)| raster.raster()[y][x] == 0)
+            .unwrap();
+        assert_eq!(fb.pixel_at(1 + x, 1 + y), [255; 3]);
+    }
+
+    #[test]
+    fn writes_single_cells() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("abc").unwrap();
+        let cursor = writer.cursor();
+        writer.write_char_at(1, 0, 'X');
+        writer.write_char_at(0, 1, 'Y');
+        writer.write_char_at(99, 99, 'Z');
+        assert_eq!((row_text(&writer, 0).as_str(), row_text(&writer, 1).as_str()), ("aXc", "Y"));
+        assert_eq!(writer.cursor(), cursor);
+        writer.clear_cell(0, 1);
+        assert_eq!(row_text(&writer, 1), "");
+        let once = writer.checksum();
+        writer.write_char_at(1, 0, 'X');
+        assert_eq!(writer.checksum(), once);
+    }
+
+    #[test]
+    fn blinking_text() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("a\x1b[5mb\x1b[25mc").unwrap();
+        assert!(writer.cells[0][1].attrs.contains(TextAttrs::BLINK));
+        let shown = writer.checksum();
+        writer.tick();
+        assert_ne!(writer.checksum(), shown);
+        assert_eq!(row_text(&writer, 0), "abc");
+        writer.tick();
+        assert_eq!(writer.checksum(), shown);
+    }
+
+    #[test]
+    fn update_field_redraws_only_changes() {
+        let mut fb = mock();
+        let mut scratch = [0; WIDTH * HEIGHT * 4];
+        let mut writer = fb.writer();
+        writer.update_field(0, 0, 4, "abc");
+        writer.flush_damage(&mut scratch);
+        writer.update_field(0, 0, 4, "abX");
+        assert_eq!(writer.damage(), Some((15, 1, 22, 17)));
+        writer.flush_damage(&mut scratch);
+
+        writer.update_field(0, 0, 2, "xyz");
+        writer.update_field(0, 5, 2, "off screen");
+        assert_eq!(row_text(&writer, 0), "xyX");
+        writer.update_field(0, 0, 4, "");
+        assert_eq!(row_text(&writer, 0), "");
+    }
+
+    #[test]
+    fn finds_text() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("hello world\nlo").unwrap();
+        assert_eq!(writer.find("world"), Some(TextPosition::Screen { col: 6, row: 0 }));
+        assert_eq!(writer.find("lo"), Some(TextPosition::Screen { col: 3, row: 0 }));
+        let all: std::vec::Vec<_> = writer.find_all("lo").collect();
+        assert_eq!(all, [TextPosition::Screen { col: 3, row: 0 }, TextPosition::Screen { col: 0, row: 1 }]);
+        assert_eq!(writer.find("o w"), Some(TextPosition::Screen { col: 4, row: 0 }));
+        assert_eq!(writer.find("missing"), None);
+        assert_eq!(writer.find(""), None);
+    }
+
+    #[test]
+    fn finds_text_that_scrolled_off() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        // Two rows fit, so "first" and "second" scroll off the top.
+        writer.write_str("first\nsecond\nthird\nfourth").unwrap();
+        assert_eq!(writer.find("first"), Some(TextPosition::Scrollback { col: 0, above: 2 }));
+        assert_eq!(writer.find("cond"), Some(TextPosition::Scrollback { col: 2, above: 1 }));
+        // Rows still on screen are only found there.
+        let all: std::vec::Vec<_> = writer.find_all("ir").collect();
+        assert_eq!(all, [TextPosition::Scrollback { col: 1, above: 2 }, TextPosition::Screen { col: 2, row: 0 }]);
+        assert_eq!(writer.find("fourth"), Some(TextPosition::Screen { col: 0, row: 1 }));
+        assert_eq!(writer.find("fifth"), None);
+
+        // Scrolling back by `above` brings the row to the top.
+        writer.scroll_back(2);
+        let scrolled = writer.checksum();
+        let mut expected = mock();
+        let mut reference = expected.writer();
+        reference.write_str("first\nsecond").unwrap();
+        assert_eq!(scrolled, reference.checksum());
+    }
+
+    #[test]
+    fn write_line_matches_drawing_char_by_char() {
+        let (fg, bg) = ([255, 255, 0], [0, 0, 255]);
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("old text\nbelow").unwrap();
+        assert_eq!(writer.write_line(2, "status", fg, bg), Err(WriterError::OutOfBounds));
+        assert_eq!(writer.write_line(0, "status", fg, bg), Ok(()));
+        assert_eq!(row_text(&writer, 0), "status");
+        assert_eq!((writer.text_color, writer.bg_color), ([255; 3], [0; 3]));
+
+        let mut expected = mock();
+        let mut reference = expected.writer();
+        reference.write_str("\nbelow").unwrap();
+        reference.set_text_color(fg);
+        reference.set_bg_color(bg);
+        let (left, right) = (reference.text_left(), reference.text_right());
+        reference.draw_filled_rect(left, 1, right - left, 18, bg);
+        let padded = "status".chars().chain(core::iter::repeat(' '));
+        for (col, c) in padded.take(reference.screen_columns()).enumerate() {
+            reference.write_char_at(col, 0, c);
+        }
+        assert_eq!(fb.bytes(), expected.bytes());
+    }
+
+    #[test]
+    fn write_line_draws_into_the_back_buffer_too() {
+        let mut back = [0; WIDTH * HEIGHT * 4];
+        let mut fb = mock();
+        let mut writer = fb.writer_with_back_buffer(&mut back);
+        writer.draw_filled_rect(0, HEIGHT - 3, 3, 3, [255, 0, 0]);
+        assert_eq!(writer.write_line(0, "status", [255; 3], [0, 0, 255]), Ok(()));
+        assert_eq!(writer.read_pixel_bytes(WIDTH - 1, 1), Some(writer.pixel_bytes([0, 0, 255])));
+        assert_eq!(fb.pixel_at(WIDTH - 1, 1), [0, 0, 255]);
+        // Drawn to the back buffer but not part of the line
+        assert_eq!(fb.pixel_at(0, HEIGHT - 1), [0; 3]);
+    }
+
+    #[test]
+    fn rows_too_big_to_stage_need_a_back_buffer() {
+        // One scanline more than the staging area holds.
+        const WIDE: usize = LINE_STAGING_LEN / (4 * 18) + 1;
+        type WideMock = MockFrameBuffer<{ WIDE * 20 * 4 }>;
+        let mut fb = WideMock::new(PixelFormat::Rgb, WIDE, 20);
+        let mut writer = fb.writer();
+        assert_eq!(writer.write_line(0, "status", [255; 3], [0, 0, 255]), Err(WriterError::NoBackBuffer));
+        assert!(fb.is_blank());
+
+        let mut back = vec![0; WIDE * 20 * 4];
+        let mut fb = WideMock::new(PixelFormat::Rgb, WIDE, 20);
+        let mut writer = fb.writer_with_back_buffer(&mut back);
+        writer.draw_filled_rect(0, 19, 3, 1, [255, 0, 0]);
+        assert_eq!(writer.write_line(0, "status", [255; 3], [0, 0, 255]), Ok(()));
+        assert_eq!(row_text(&writer, 0), "status");
+        assert_eq!(fb.pixel_at(WIDE - 2, 1), [0, 0, 255]);
+        assert_eq!(fb.pixel_at(0, 19), [0; 3]);
+    }
+}
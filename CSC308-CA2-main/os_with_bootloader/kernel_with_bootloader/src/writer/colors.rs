@@ -0,0 +1,143 @@
+//! Named colors, a helper for writing colors as `0xRRGGBB` literals and the
+//! conversion of colors into framebuffer pixels.
+
+use bootloader_api::info::PixelFormat;
+
+use super::{luminance, scale_channel};
+
+/// Unpacks a `0xRRGGBB` color. The top byte is ignored.
+pub const fn rgb(hex: u32) -> [u8; 3] {
+    [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8]
+}
+
+pub const BLACK: [u8; 3] = rgb(0x000000);
+pub const WHITE: [u8; 3] = rgb(0xffffff);
+pub const GRAY: [u8; 3] = rgb(0x808080);
+pub const RED: [u8; 3] = rgb(0xff0000);
+pub const GREEN: [u8; 3] = rgb(0x00ff00);
+pub const BLUE: [u8; 3] = rgb(0x0000ff);
+pub const YELLOW: [u8; 3] = rgb(0xffff00);
+pub const CYAN: [u8; 3] = rgb(0x00ffff);
+pub const MAGENTA: [u8; 3] = rgb(0xff00ff);
+pub const ORANGE: [u8; 3] = rgb(0xff8000);
+
+/// An RGB color, as the writer converts it into the byte layout of a
+/// framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub u8, pub u8, pub u8);
+
+impl From<[u8; 3]> for Color {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        Color(r, g, b)
+    }
+}
+
+impl From<Color> for [u8; 3] {
+    fn from(Color(r, g, b): Color) -> Self {
+        [r, g, b]
+    }
+}
+
+impl Color {
+    /// The pixel bytes of this color, scaled by `intensity / 255`, for a
+    /// framebuffer in `format` with `bytes_per_pixel` bytes per pixel. Only
+    /// the first `bytes_per_pixel` bytes are meaningful. 2 byte Rgb, Bgr and
+    /// `Unknown` pixels are packed as RGB565 (BGR565 for Bgr), U8 pixels get
+    /// the luminance. Formats the writer doesn't support come out all zero.
+    pub fn to_bytes(&self, format: PixelFormat, bytes_per_pixel: usize, intensity: u8) -> [u8; 4] {
+        let [r, g, b] = <[u8; 3]>::from(*self).map(|channel| scale_channel(channel, intensity));
+        if bytes_per_pixel == 2 && format != PixelFormat::U8 {
+            let (high, low) = match format {
+                PixelFormat::Bgr => (b, r),
+                _ => (r, b),
+            };
+            return pack_rgb565(high, g, low);
+        }
+        match format {
+            PixelFormat::Rgb => [r, g, b, 0],
+            PixelFormat::Bgr => [b, g, r, 0],
+            PixelFormat::U8 => [luminance([r, g, b]), 0, 0, 0],
+            PixelFormat::Unknown { red_position, green_position, blue_position } => {
+                let value = (r as u32) << red_position
+                    | (g as u32) << green_position
+                    | (b as u32) << blue_position;
+                value.to_le_bytes()
+            }
+            _ => [0; 4],
+        }
+    }
+
+    /// Reads a color back from pixel bytes laid out as by [Self::to_bytes]
+    /// with full intensity. Channels lost in packing, like the low bits of
+    /// RGB565, come back as 0; U8 pixels come back gray.
+    pub fn from_bytes(bytes: [u8; 4], format: PixelFormat, bytes_per_pixel: usize) -> Self {
+        if bytes_per_pixel == 2 && format != PixelFormat::U8 {
+            let packed = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let high = ((packed >> 11) as u8) << 3;
+            let green = ((packed >> 5 & 0x3f) as u8) << 2;
+            let low = ((packed & 0x1f) as u8) << 3;
+            return match format {
+                PixelFormat::Bgr => Color(low, green, high),
+                _ => Color(high, green, low),
+            };
+        }
+        match format {
+            PixelFormat::Rgb => Color(bytes[0], bytes[1], bytes[2]),
+            PixelFormat::Bgr => Color(bytes[2], bytes[1], bytes[0]),
+            PixelFormat::U8 => Color(bytes[0], bytes[0], bytes[0]),
+            PixelFormat::Unknown { red_position, green_position, blue_position } => {
+                let value = u32::from_le_bytes(bytes);
+                let channel = |position: u8| (value >> position) as u8;
+                Color(channel(red_position), channel(green_position), channel(blue_position))
+            }
+            _ => Color(0, 0, 0),
+        }
+    }
+}
+
+/// Packs a color into 16-bit 5-6-5 layout, little-endian. `high` goes in the
+/// top 5 bits and `low` in the bottom 5.
+fn pack_rgb565(high: u8, green: u8, low: u8) -> [u8; 4] {
+    let packed = ((high as u16 >> 3) << 11) | ((green as u16 >> 2) << 5) | (low as u16 >> 3);
+    let [lo, hi] = packed.to_le_bytes();
+    [lo, hi, 0, 0]
+}
+
+#[cfg(test)]
+mod tests {
+    use bootloader_api::info::PixelFormat;
+
+    use super::{rgb, Color, ORANGE};
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(rgb(0x123456), [0x12, 0x34, 0x56]);
+        assert_eq!(ORANGE, [255, 128, 0]);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let color = Color(0x11, 0x22, 0x33);
+        let bgr_in_high_bytes = PixelFormat::Unknown { red_position: 16, green_position: 8, blue_position: 24 };
+        for (format, bytes) in [
+            (PixelFormat::Rgb, [0x11, 0x22, 0x33, 0]),
+            (PixelFormat::Bgr, [0x33, 0x22, 0x11, 0]),
+            (bgr_in_high_bytes, [0, 0x22, 0x11, 0x33]),
+        ] {
+            assert_eq!(color.to_bytes(format, 4, 255), bytes, "{:?}", format);
+            assert_eq!(Color::from_bytes(bytes, format, 4), color, "{:?}", format);
+        }
+        assert_eq!(color.to_bytes(PixelFormat::Rgb, 4, 0), [0; 4]);
+        let [gray, ..] = Color(255, 255, 255).to_bytes(PixelFormat::U8, 1, 255);
+        assert_eq!(Color::from_bytes([gray, 0, 0, 0], PixelFormat::U8, 1), Color(255, 255, 255));
+    }
+
+    #[test]
+    fn packs_565() {
+        assert_eq!(Color(255, 128, 0).to_bytes(PixelFormat::Rgb, 2, 255), [0x00, 0xfc, 0, 0]);
+        assert_eq!(Color(255, 128, 0).to_bytes(PixelFormat::Bgr, 2, 255), [0x1f, 0x04, 0, 0]);
+        // The low bits are lost.
+        assert_eq!(Color::from_bytes([0x00, 0xfc, 0, 0], PixelFormat::Rgb, 2), Color(248, 128, 0));
+        assert_eq!(Color::from_bytes([0x1f, 0x04, 0, 0], PixelFormat::Bgr, 2), Color(248, 128, 0));
+    }
+}
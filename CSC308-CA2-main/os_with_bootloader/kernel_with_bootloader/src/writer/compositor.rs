@@ -0,0 +1,142 @@
+use super::FrameBufferWriter;
+
+/// Default number of layers a [Compositor] has room for.
+pub const MAX_LAYERS: usize = 8;
+
+/// Draws one layer of a frame, given the frame's tick.
+pub type DrawLayer<'l, 'a> = &'l mut dyn FnMut(&mut FrameBufferWriter<'a>, u64);
+
+struct Layer<'l, 'a> {
+    z: i32,
+    draw: DrawLayer<'l, 'a>,
+}
+
+/// Redraws a scene made of up to `N` layers each frame, e.g. from a timer
+/// interrupt. Layers are drawn from the lowest z to the highest, so higher
+/// ones cover lower ones; layers with the same z are drawn in the order they
+/// were added.
+///
+/// ```no_run
+/// use kernel_with_bootloader::writer::{Compositor, FrameBufferWriter};
+///
+/// fn animate(writer: &mut FrameBufferWriter, tick: u64) {
+///     let mut background = |w: &mut FrameBufferWriter, _: u64| w.draw_test_pattern();
+///     let mut ball = |w: &mut FrameBufferWriter, tick: u64| {
+///         w.draw_filled_circle(20 + tick as usize % 200, 50, 10, [255, 255, 255])
+///     };
+///     let mut compositor: Compositor = Compositor::new();
+///     compositor.add_layer(0, &mut background);
+///     compositor.add_layer(1, &mut ball);
+///     compositor.render_frame(writer, tick);
+/// }
+/// ```
+pub struct Compositor<'l, 'a, const N: usize = MAX_LAYERS> {
+    /// The first `len` entries are set, sorted by z.
+    layers: [Option<Layer<'l, 'a>>; N],
+    len: usize,
+}
+
+impl<'l, 'a, const N: usize> Compositor<'l, 'a, N> {
+    pub fn new() -> Self {
+        Self { layers: [const { None }; N], len: 0 }
+    }
+
+    /// Adds a layer drawn by `draw` at depth `z`. Returns false if all `N`
+    /// layers are taken.
+    pub fn add_layer(&mut self, z: i32, draw: DrawLayer<'l, 'a>) -> bool {
+        if self.len == N {
+            return false;
+        }
+        let index = self.layers[..self.len]
+            .iter()
+            .position(|layer| layer.as_ref().is_some_and(|layer| layer.z > z))
+            .unwrap_or(self.len);
+        // Moves the free slot at `len` to `index`.
+        self.layers[index..=self.len].rotate_right(1);
+        self.layers[index] = Some(Layer { z, draw });
+        self.len += 1;
+        true
+    }
+
+    /// Removes every layer.
+    pub fn clear_layers(&mut self) {
+        self.layers.iter_mut().for_each(|layer| *layer = None);
+        self.len = 0;
+    }
+
+    /// Draws one frame: fills the screen with the background color, draws
+    /// every layer and presents the result. With a back buffer the frame only
+    /// shows up once it's complete; without one it's drawn straight to the
+    /// screen.
+    pub fn render_frame(&mut self, writer: &mut FrameBufferWriter<'a>, tick: u64) {
+        writer.fill_screen(writer.bg_color);
+        for layer in self.layers[..self.len].iter_mut().flatten() {
+            (layer.draw)(writer, tick);
+        }
+        writer.present();
+    }
+}
+
+impl<const N: usize> Default for Compositor<'_, '_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::FrameBufferWriter;
+    use super::Compositor;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    type Mock = MockFrameBuffer<{ WIDTH * HEIGHT * 4 }>;
+
+    #[test]
+    fn draws_layers_by_depth() {
+        let mut back = [0; WIDTH * HEIGHT * 4];
+        let mut fb = Mock::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let mut writer = fb.writer_with_back_buffer(&mut back);
+        let mut ticks = 0;
+        let mut top = |w: &mut FrameBufferWriter, tick: u64| {
+            ticks = tick;
+            w.draw_filled_rect(0, 0, 10, 10, RED);
+        };
+        let mut bottom = |w: &mut FrameBufferWriter, _: u64| w.draw_filled_rect(5, 5, 10, 10, BLUE);
+        let mut stray = |w: &mut FrameBufferWriter, _: u64| w.draw_filled_rect(0, 0, WIDTH, HEIGHT, BLUE);
+        let mut compositor: Compositor<2> = Compositor::new();
+        assert!(compositor.add_layer(5, &mut top));
+        assert!(compositor.add_layer(-1, &mut bottom));
+        assert!(!compositor.add_layer(0, &mut stray));
+        compositor.render_frame(&mut writer, 42);
+        assert!(writer.read_pixel_bytes(7, 7) == Some(writer.pixel_bytes(RED)));
+        assert!(writer.read_pixel_bytes(12, 12) == Some(writer.pixel_bytes(BLUE)));
+        compositor.clear_layers();
+        compositor.render_frame(&mut writer, 43);
+        assert_eq!(ticks, 42);
+        assert!(fb.is_blank());
+    }
+
+    #[test]
+    fn frames_show_up_whole() {
+        let mut back = [0; WIDTH * HEIGHT * 4];
+        let mut fb = Mock::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let mut writer = fb.writer_with_back_buffer(&mut back);
+        let blank = writer.checksum();
+        let mut layer = |w: &mut FrameBufferWriter, _: u64| {
+            // Nothing reaches the screen while the frame is drawn.
+            assert_eq!(w.checksum(), blank);
+            w.draw_filled_rect(0, 0, 10, 10, RED);
+        };
+        let mut compositor: Compositor = Compositor::default();
+        compositor.add_layer(0, &mut layer);
+        compositor.render_frame(&mut writer, 0);
+        assert_eq!(fb.pixel_at(9, 9), RED);
+    }
+}
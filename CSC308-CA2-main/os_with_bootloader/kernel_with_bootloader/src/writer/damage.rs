@@ -0,0 +1,97 @@
+use super::FrameBufferWriter;
+
+/// Keeping track of the part of the screen drawn to since the last
+/// [FrameBufferWriter::flush_damage], so only that part has to be copied.
+impl FrameBufferWriter<'_> {
+    /// The smallest rectangle around everything drawn since the last
+    /// [Self::flush_damage], as (x0, y0, x1, y1) with x1 and y1 excluded.
+    /// These are framebuffer coordinates, i.e. before any rotation.
+    pub fn damage(&self) -> Option<(usize, usize, usize, usize)> {
+        self.dirty
+    }
+
+    /// Copies the pixels drawn since the last call from the buffer the writer
+    /// draws to into `dst`, which has the same layout, e.g. the framebuffer of
+    /// a double buffered setup or a copy sent over the network. Every other
+    /// byte of `dst` is left alone.
+    pub fn flush_damage(&mut self, dst: &mut [u8]) {
+        let Some((x0, y0, x1, y1)) = self.dirty.take() else {
+            return;
+        };
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let src = self.buffer();
+        let len = src.len().min(dst.len());
+        for y in y0..y1 {
+            let start = ((y * self.info.stride + x0) * bytes_per_pixel).min(len);
+            let end = ((y * self.info.stride + x1) * bytes_per_pixel).min(len);
+            dst[start..end].copy_from_slice(&src[start..end]);
+        }
+    }
+
+    /// Adds the rectangle from (x0, y0) up to, but excluding, (x1, y1) to the
+    /// damage.
+    pub(super) fn mark_dirty(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        let (x1, y1) = (x1.min(self.info.width), y1.min(self.info.height));
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        self.dirty = Some(match self.dirty {
+            Some((dx0, dy0, dx1, dy1)) => (dx0.min(x0), dy0.min(y0), dx1.max(x1), dy1.max(y1)),
+            None => (x0, y0, x1, y1),
+        });
+    }
+
+    /// Marks the whole screen as drawn to.
+    pub(super) fn mark_all_dirty(&mut self) {
+        self.mark_dirty(0, 0, self.info.width, self.info.height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::font_constants::{CHAR_RASTER_HEIGHT, CHAR_RASTER_WIDTH};
+    use super::super::mock::MockFrameBuffer;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+
+    fn pixel(bytes: &[u8], x: usize, y: usize) -> &[u8] {
+        &bytes[(y * WIDTH + x) * 4..][..4]
+    }
+
+    #[test]
+    fn copies_only_what_was_drawn() {
+        let mut dst = [7; WIDTH * HEIGHT * 4];
+        let mut fb = MockFrameBuffer::<{ WIDTH * HEIGHT * 4 }>::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let mut writer = fb.writer();
+        // Creating the writer clears the whole screen.
+        assert_eq!(writer.damage(), Some((0, 0, WIDTH, HEIGHT)));
+        writer.flush_damage(&mut [0; WIDTH * HEIGHT * 4]);
+        assert_eq!(writer.damage(), None);
+
+        writer.draw_filled_rect(10, 5, 4, 3, [255, 0, 0]);
+        writer.set_pixel(30, 20, [255, 0, 0]);
+        writer.set_pixel(WIDTH, 0, [255, 0, 0]);
+        assert_eq!(writer.damage(), Some((10, 5, 31, 21)));
+        writer.flush_damage(&mut dst);
+        assert_eq!(writer.damage(), None);
+        assert_eq!((pixel(&dst, 10, 5), pixel(&dst, 30, 20), pixel(&dst, 20, 10)), (&[255, 0, 0, 0][..], &[255, 0, 0, 0][..], &[0; 4][..]));
+        assert_eq!((pixel(&dst, 9, 5), pixel(&dst, 31, 20), pixel(&dst, 10, 21)), (&[7; 4][..], &[7; 4][..], &[7; 4][..]));
+
+        // Nothing drawn since, nothing copied.
+        writer.flush_damage(&mut dst);
+        assert_eq!(pixel(&dst, 9, 5), &[7; 4]);
+    }
+
+    #[test]
+    fn one_char_damages_its_cell() {
+        let mut fb = MockFrameBuffer::<{ WIDTH * HEIGHT * 4 }>::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let mut writer = fb.writer();
+        writer.flush_damage(&mut [0; WIDTH * HEIGHT * 4]);
+        writer.write_char('A');
+        let (width, height) = (CHAR_RASTER_WIDTH, CHAR_RASTER_HEIGHT.val());
+        assert_eq!(writer.damage(), Some((1, 1, 1 + width, 1 + height)));
+    }
+}
@@ -0,0 +1,216 @@
+use super::palette::palette_color;
+
+/// Longest parameter string we keep for a single CSI sequence.
+/// Longer sequences are still consumed, but ignored.
+const MAX_SEQUENCE_LEN: usize = 32;
+
+/// Most numeric parameters a single CSI sequence can carry.
+pub const MAX_PARAMS: usize = 16;
+
+/// Parses the color following an SGR 38 or 48 code: either truecolor
+/// `2;r;g;b`, with each component clamped to 255, or palette index `5;n`.
+/// Returns the color and the parameters after it.
+pub fn parse_extended_color(params: &[u16]) -> Option<([u8; 3], &[u16])> {
+    match params {
+        [5, n, rest @ ..] => {
+            let n = u8::try_from(*n).ok()?;
+            Some((palette_color(n), rest))
+        }
+        [2, r, g, b, rest @ ..] => {
+            let clamp = |v: u16| v.min(255) as u8;
+            Some(([clamp(*r), clamp(*g), clamp(*b)], rest))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+/// A complete `ESC [ params final` sequence.
+pub struct CsiSequence {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    pub final_char: char,
+}
+
+impl CsiSequence {
+    /// The numeric parameters. An omitted parameter reads as 0.
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+}
+
+/// What the writer should do with a char fed through the escape parser.
+pub enum Parsed {
+    /// A regular char to draw or a control char to handle.
+    Char(char),
+    /// The char was part of an unfinished escape sequence.
+    Pending,
+    /// The char completed a CSI sequence.
+    Csi(CsiSequence),
+}
+
+/// Holds a partially received escape sequence, so a sequence split across
+/// several `write_str` calls is still recognized.
+pub struct EscapeParser {
+    state: State,
+    buffer: [u8; MAX_SEQUENCE_LEN],
+    len: usize,
+    overflowed: bool,
+}
+
+impl EscapeParser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            buffer: [0; MAX_SEQUENCE_LEN],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Whether an escape sequence has been started but not finished.
+    pub fn is_pending(&self) -> bool {
+        !matches!(self.state, State::Ground)
+    }
+
+    pub fn feed(&mut self, c: char) -> Parsed {
+        match self.state {
+            State::Ground if c == '\x1b' => {
+                self.state = State::Escape;
+                Parsed::Pending
+            }
+            State::Ground => Parsed::Char(c),
+            State::Escape => {
+                // Only CSI sequences are supported; a lone ESC followed by
+                // anything else is dropped along with that char.
+                self.state = if c == '[' { State::Csi } else { State::Ground };
+                self.len = 0;
+                self.overflowed = false;
+                Parsed::Pending
+            }
+            State::Csi => match c {
+                // parameter and intermediate bytes
+                '\x20'..='\x3f' => {
+                    if self.len < MAX_SEQUENCE_LEN {
+                        self.buffer[self.len] = c as u8;
+                        self.len += 1;
+                    } else {
+                        self.overflowed = true;
+                    }
+                    Parsed::Pending
+                }
+                // final byte
+                '\x40'..='\x7e' => {
+                    self.state = State::Ground;
+                    if self.overflowed {
+                        Parsed::Pending
+                    } else {
+                        Parsed::Csi(self.parse(c))
+                    }
+                }
+                // anything else aborts the sequence and is handled normally
+                c => {
+                    self.state = State::Ground;
+                    Parsed::Char(c)
+                }
+            },
+        }
+    }
+
+    fn parse(&self, final_char: char) -> CsiSequence {
+        let mut sequence = CsiSequence {
+            params: [0; MAX_PARAMS],
+            len: 0,
+            final_char,
+        };
+        if self.len == 0 {
+            return sequence;
+        }
+        for param in self.buffer[..self.len].split(|&b| b == b';') {
+            if sequence.len == MAX_PARAMS {
+                break;
+            }
+            sequence.params[sequence.len] = param
+                .iter()
+                .filter(|b| b.is_ascii_digit())
+                .fold(0u16, |acc, b| acc.saturating_mul(10).saturating_add((b - b'0') as u16));
+            sequence.len += 1;
+        }
+        sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::palette::palette_color;
+    use super::{parse_extended_color, EscapeParser, Parsed, MAX_PARAMS};
+
+    /// Feeds `s` and returns the final char and parameters of the one CSI
+    /// sequence it completes, checking that everything else is pending.
+    fn csi(parser: &mut EscapeParser, s: &str) -> Option<(char, [u16; MAX_PARAMS], usize)> {
+        let mut found = None;
+        for c in s.chars() {
+            match parser.feed(c) {
+                Parsed::Pending => {}
+                Parsed::Char(c) => panic!("{:?} drawn", c),
+                Parsed::Csi(sequence) => {
+                    let mut params = [0; MAX_PARAMS];
+                    params[..sequence.params().len()].copy_from_slice(sequence.params());
+                    found = Some((sequence.final_char, params, sequence.params().len()));
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn parses_parameters() {
+        let mut parser = EscapeParser::new();
+        let (final_char, params, len) = csi(&mut parser, "\x1b[1;;31m").unwrap();
+        assert_eq!((final_char, &params[..len]), ('m', &[1, 0, 31][..]));
+        let (final_char, _, len) = csi(&mut parser, "\x1b[H").unwrap();
+        assert_eq!((final_char, len), ('H', 0));
+        assert!(!parser.is_pending());
+    }
+
+    #[test]
+    fn keeps_sequences_across_feeds() {
+        let mut parser = EscapeParser::new();
+        assert!(csi(&mut parser, "\x1b[3").is_none());
+        assert!(parser.is_pending());
+        let (final_char, params, len) = csi(&mut parser, "8;5;196m").unwrap();
+        assert_eq!((final_char, &params[..len]), ('m', &[38, 5, 196][..]));
+    }
+
+    #[test]
+    fn drops_what_it_cant_handle() {
+        let mut parser = EscapeParser::new();
+        // Not a CSI sequence: the ESC and the char after it are dropped.
+        assert!(matches!(parser.feed('\x1b'), Parsed::Pending));
+        assert!(matches!(parser.feed('7'), Parsed::Pending));
+        assert!(matches!(parser.feed('a'), Parsed::Char('a')));
+        // A control char aborts a sequence and is handled as usual.
+        assert!(csi(&mut parser, "\x1b[12").is_none());
+        assert!(matches!(parser.feed('\n'), Parsed::Char('\n')));
+        assert!(!parser.is_pending());
+        // Too long to keep: consumed but ignored.
+        let long = "\x1b[1111111111111111111111111111111111111111m";
+        assert!(csi(&mut parser, long).is_none());
+        assert!(matches!(parser.feed('b'), Parsed::Char('b')));
+    }
+
+    #[test]
+    fn extended_colors() {
+        assert_eq!(parse_extended_color(&[5, 196, 1]), Some((palette_color(196), &[1][..])));
+        assert_eq!(parse_extended_color(&[2, 1, 300, 3]), Some(([1, 255, 3], &[][..])));
+        assert_eq!(parse_extended_color(&[5, 256]), None);
+        assert_eq!(parse_extended_color(&[2, 1, 2]), None);
+        assert_eq!(parse_extended_color(&[]), None);
+    }
+}
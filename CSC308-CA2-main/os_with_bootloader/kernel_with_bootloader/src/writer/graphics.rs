@@ -0,0 +1,752 @@
+use super::{blend_channel, builtin_glyph, get_char_raster, scale_channel, Color, FrameBufferWriter, Orientation};
+
+/// Returned by [FrameBufferWriter::blit] and
+/// [FrameBufferWriter::draw_image_rgba] when the pixel slice doesn't hold
+/// exactly `w * h` pixels. The lengths are counted in slice elements, i.e.
+/// pixels for `blit` and bytes for `draw_image_rgba`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongPixelCount {
+    pub expected: usize,
+    pub actual: usize,
+}
+
+/// Basic shapes drawn straight into the framebuffer. All of them clip to the
+/// visible area, so any coordinates are safe to pass in.
+impl FrameBufferWriter<'_> {
+    /// Fills a `w` x `h` rectangle whose top left corner is at (x, y).
+    pub fn draw_filled_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: [u8; 3]) {
+        let x_end = x.saturating_add(w).min(self.width());
+        let y_end = y.saturating_add(h).min(self.height());
+        let color = self.pixel_bytes(color);
+        for y in y..y_end {
+            for x in x..x_end {
+                self.write_pixel_bytes(x, y, color);
+            }
+        }
+    }
+
+    /// Fills a `w` x `h` rectangle whose top left corner is at (x, y) with a
+    /// linear gradient from `start` to `end`: from the top row to the bottom
+    /// one if `vertical`, else from the left column to the right one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_gradient_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        start: [u8; 3],
+        end: [u8; 3],
+        vertical: bool,
+    ) {
+        let steps = if vertical { h } else { w };
+        for step in 0..steps {
+            let color = lerp_color(start, end, step, steps);
+            if vertical {
+                self.draw_filled_rect(x, y.saturating_add(step), w, 1, color);
+            } else {
+                self.draw_filled_rect(x.saturating_add(step), y, 1, h, color);
+            }
+        }
+    }
+
+    /// Draws the 1px outline of a `w` x `h` rectangle whose top left corner
+    /// is at (x, y).
+    pub fn draw_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: [u8; 3]) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        let right = x.saturating_add(w - 1);
+        let bottom = y.saturating_add(h - 1);
+        self.draw_filled_rect(x, y, w, 1, color);
+        self.draw_filled_rect(x, bottom, w, 1, color);
+        self.draw_filled_rect(x, y, 1, h, color);
+        self.draw_filled_rect(right, y, 1, h, color);
+    }
+
+    /// Draws a 1px line from (x0, y0) to (x1, y1), both ends included, using
+    /// Bresenham's algorithm. The line is clipped to the screen first, so
+    /// only its on-screen part is walked.
+    pub fn draw_line(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, color: [u8; 3]) {
+        let Some(((mut x, mut y), (x1, y1))) = clip_line((x0, y0), (x1, y1), self.width(), self.height()) else {
+            return;
+        };
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let step_x = if x < x1 { 1 } else { -1 };
+        let step_y = if y < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let color = self.pixel_bytes(color);
+        loop {
+            self.write_pixel_bytes(x as usize, y as usize, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws the 1px outline of a circle with radius `r` around (cx, cy),
+    /// using the midpoint circle algorithm.
+    pub fn draw_circle(&mut self, cx: usize, cy: usize, r: usize, color: [u8; 3]) {
+        let color = self.pixel_bytes(color);
+        let (cx, cy) = (cx as isize, cy as isize);
+        for_each_octant_point(r, |x, y| {
+            for (dx, dy) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+                self.plot(cx + dx, cy + dy, color);
+            }
+        });
+    }
+
+    /// Fills a circle with radius `r` around (cx, cy).
+    pub fn draw_filled_circle(&mut self, cx: usize, cy: usize, r: usize, color: [u8; 3]) {
+        let (cx, cy) = (cx as isize, cy as isize);
+        for_each_octant_point(r, |x, y| {
+            self.fill_span(cx - x, cx + x, cy + y, color);
+            self.fill_span(cx - x, cx + x, cy - y, color);
+            self.fill_span(cx - y, cx + y, cy + x, color);
+            self.fill_span(cx - y, cx + y, cy - x, color);
+        });
+    }
+
+    /// Fills a `w` x `h` rectangle whose top left corner is at (x, y), with
+    /// its corners rounded off to quarter circles of radius `radius`, e.g.
+    /// for a UI panel. A radius over half the shorter side is cut down to it.
+    pub fn draw_rounded_rect(&mut self, x: usize, y: usize, w: usize, h: usize, radius: usize, color: [u8; 3]) {
+        let r = radius.min(w / 2).min(h / 2);
+        if r == 0 || w == 0 || h == 0 {
+            self.draw_filled_rect(x, y, w, h, color);
+            return;
+        }
+        // The rows between the corners are full width.
+        self.draw_filled_rect(x, y.saturating_add(r), w, h - 2 * r, color);
+        // Above and below them, spans reach from the left corner circle to
+        // the right one.
+        let (left, right) = (x.saturating_add(r) as isize, x.saturating_add(w - 1 - r) as isize);
+        let (top, bottom) = (y.saturating_add(r) as isize, y.saturating_add(h - 1 - r) as isize);
+        for_each_octant_point(r, |dx, dy| {
+            self.fill_span(left - dx, right + dx, top - dy, color);
+            self.fill_span(left - dy, right + dy, top - dx, color);
+            self.fill_span(left - dx, right + dx, bottom + dy, color);
+            self.fill_span(left - dy, right + dy, bottom + dx, color);
+        });
+    }
+
+    /// Draws the 1px outline of the triangle with corners `p0`, `p1` and `p2`.
+    pub fn draw_triangle(&mut self, p0: (usize, usize), p1: (usize, usize), p2: (usize, usize), color: [u8; 3]) {
+        self.draw_line(p0.0, p0.1, p1.0, p1.1, color);
+        self.draw_line(p1.0, p1.1, p2.0, p2.1, color);
+        self.draw_line(p2.0, p2.1, p0.0, p0.1, color);
+    }
+
+    /// Fills the triangle with corners `p0`, `p1` and `p2`, edges included.
+    /// A pixel is filled if it lies on the inner side of every edge, which
+    /// takes only integer math.
+    pub fn fill_triangle(&mut self, p0: (usize, usize), p1: (usize, usize), p2: (usize, usize), color: [u8; 3]) {
+        let area = edge(p0, p1, p2);
+        if area == 0 {
+            // All corners on one line
+            self.draw_triangle(p0, p1, p2, color);
+            return;
+        }
+        let x_end = p0.0.max(p1.0).max(p2.0).saturating_add(1).min(self.width());
+        let y_end = p0.1.max(p1.1).max(p2.1).saturating_add(1).min(self.height());
+        let color = self.pixel_bytes(color);
+        for y in p0.1.min(p1.1).min(p2.1)..y_end {
+            for x in p0.0.min(p1.0).min(p2.0)..x_end {
+                let p = (x, y);
+                let weights = [edge(p1, p2, p), edge(p2, p0, p), edge(p0, p1, p)];
+                // Inside means the same sign as the whole triangle's area.
+                if weights.iter().all(|&w| w == 0 || (w > 0) == (area > 0)) {
+                    self.write_pixel_bytes(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Draws `color` over the pixel at (x, y) with opacity `alpha`: 255
+    /// replaces the pixel, 0 leaves it as it is and anything in between mixes
+    /// the two, e.g. for translucent panels. Returns false if (x, y) is
+    /// off-screen.
+    pub fn blend_pixel(&mut self, x: usize, y: usize, color: [u8; 3], alpha: u8) -> bool {
+        let Some(old) = self.read_pixel_bytes(x, y) else {
+            return false;
+        };
+        let (format, bytes_per_pixel) = (self.info.pixel_format, self.info.bytes_per_pixel);
+        // The old pixel already has the brightness applied, so apply it to
+        // the new color only.
+        let old: [u8; 3] = Color::from_bytes(old, format, bytes_per_pixel).into();
+        let new = color.map(|channel| scale_channel(channel, self.brightness));
+        let mixed = [0, 1, 2].map(|i| blend_channel(old[i], new[i], alpha));
+        self.write_pixel_bytes(x, y, Color::from(mixed).to_bytes(format, bytes_per_pixel, 255))
+    }
+
+    /// Draws `c` in the current font with its top left corner at (x, y),
+    /// each font pixel as a `scale` x `scale` block, e.g. for a big numeral.
+    /// Ignores the text scale and colors and leaves the write position
+    /// alone. Only the glyph itself is drawn, over what's already there.
+    pub fn draw_char_scaled(&mut self, x: usize, y: usize, c: char, scale: usize, color: [u8; 3]) {
+        let raster;
+        let glyph = match self.font {
+            Some(font) => font.glyph(c),
+            None => {
+                raster = get_char_raster(c);
+                builtin_glyph(raster.as_deref())
+            }
+        };
+        let (width, height) = (self.width(), self.height());
+        for gy in 0..glyph.height() {
+            for gx in 0..glyph.width() {
+                let intensity = glyph.intensity(gx, gy);
+                if intensity == 0 {
+                    continue;
+                }
+                let left = x.saturating_add(gx * scale);
+                let top = y.saturating_add(gy * scale);
+                for py in top..top.saturating_add(scale).min(height) {
+                    for px in left..left.saturating_add(scale).min(width) {
+                        self.blend_pixel(px, py, color, intensity);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies a `w` x `h` image, given as rows of RGB pixels from top to
+    /// bottom, so that its top left corner lands at (x, y).
+    pub fn blit(
+        &mut self,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        pixels: &[[u8; 3]],
+    ) -> Result<(), WrongPixelCount> {
+        if pixels.len() != w * h {
+            return Err(WrongPixelCount { expected: w * h, actual: pixels.len() });
+        }
+        if w == 0 {
+            return Ok(());
+        }
+        for (row, row_pixels) in pixels.chunks_exact(w).enumerate() {
+            self.blit_row(x, y.saturating_add(row), row_pixels);
+        }
+        Ok(())
+    }
+
+    /// Copies one row of RGB pixels so that it starts at (x, y), dropping
+    /// whatever falls off the screen. [Self::blit] is made of these.
+    pub(super) fn blit_row(&mut self, x: usize, y: usize, pixels: &[[u8; 3]]) {
+        if y >= self.height() {
+            return;
+        }
+        let visible = self.width().saturating_sub(x).min(pixels.len());
+        for (col, &color) in pixels[..visible].iter().enumerate() {
+            self.set_pixel(x + col, y, color);
+        }
+    }
+
+    /// Like [Self::blit], but for an image with transparency given as RGBA
+    /// bytes, 4 per pixel: each pixel is blended over the screen by its
+    /// alpha byte like [Self::blend_pixel], e.g. for an icon. Pixels with
+    /// alpha 0 are skipped.
+    pub fn draw_image_rgba(
+        &mut self,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        rgba: &[u8],
+    ) -> Result<(), WrongPixelCount> {
+        if rgba.len() != w * h * 4 {
+            return Err(WrongPixelCount { expected: w * h * 4, actual: rgba.len() });
+        }
+        if w == 0 {
+            return Ok(());
+        }
+        for (row, row_bytes) in rgba.chunks_exact(w * 4).enumerate() {
+            for (col, pixel) in row_bytes.chunks_exact(4).enumerate() {
+                if pixel[3] == 0 {
+                    continue;
+                }
+                let color = [pixel[0], pixel[1], pixel[2]];
+                self.blend_pixel(x.saturating_add(col), y.saturating_add(row), color, pixel[3]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the `w` x `h` block of pixels whose top left corner is at `src`
+    /// so that its top left corner lands at `dst`, e.g. to drag a window or
+    /// scroll part of the screen. The blocks may overlap. The block is
+    /// clipped so that both copies are on screen.
+    pub fn copy_rect(&mut self, src: (usize, usize), dst: (usize, usize), w: usize, h: usize) {
+        let (width, height) = (self.width(), self.height());
+        if src.0.max(dst.0) >= width || src.1.max(dst.1) >= height {
+            return;
+        }
+        let w = w.min(width - src.0.max(dst.0));
+        let h = h.min(height - src.1.max(dst.1));
+        if w == 0 || h == 0 {
+            return;
+        }
+        if self.orientation != Orientation::Normal {
+            self.move_rect(src.0, src.1, dst.0, dst.1, w, h);
+            return;
+        }
+        self.mark_dirty(dst.0, dst.1, dst.0 + w, dst.1 + h);
+        let row_bytes = self.info.stride * self.info.bytes_per_pixel;
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let buffer = self.buffer_mut();
+        let copy_row = |buffer: &mut [u8], row: usize| {
+            let from = (src.1 + row) * row_bytes + src.0 * bytes_per_pixel;
+            let to = (dst.1 + row) * row_bytes + dst.0 * bytes_per_pixel;
+            let len = w * bytes_per_pixel;
+            if from.max(to) + len <= buffer.len() {
+                // copy_within handles rows that overlap themselves.
+                buffer.copy_within(from..from + len, to);
+            }
+        };
+        // Moving down, go from the bottom row up (and top down otherwise), so
+        // no row is overwritten before it's copied.
+        if dst.1 > src.1 {
+            for row in (0..h).rev() {
+                copy_row(buffer, row);
+            }
+        } else {
+            for row in 0..h {
+                copy_row(buffer, row);
+            }
+        }
+    }
+
+    /// Fills every visible pixel with one color. Faster than
+    /// [Self::draw_filled_rect]: only the first row is drawn pixel by pixel,
+    /// the others are copied from it. Doesn't touch the text or the cursor.
+    pub fn fill_screen(&mut self, color: [u8; 3]) {
+        let color = self.pixel_bytes(color);
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let row_bytes = self.info.stride * bytes_per_pixel;
+        // Every pixel gets the same color, so the rotation doesn't matter.
+        let width_bytes = self.info.width * bytes_per_pixel;
+        let visible_bytes = self.info.height * row_bytes;
+        self.mark_all_dirty();
+        let buffer = self.buffer_mut();
+        let visible_bytes = visible_bytes.min(buffer.len());
+        let (first_row, rest) = buffer[..visible_bytes].split_at_mut(row_bytes.min(visible_bytes));
+        let width_bytes = width_bytes.min(first_row.len());
+        for pixel in first_row[..width_bytes].chunks_exact_mut(bytes_per_pixel) {
+            pixel.copy_from_slice(&color[..bytes_per_pixel]);
+        }
+        for row in rest.chunks_mut(row_bytes) {
+            let len = row.len().min(width_bytes);
+            row[..len].copy_from_slice(&first_row[..len]);
+        }
+    }
+
+    /// Draws vertical color bars with a 1px white border around the screen.
+    /// Wrong colors point at a pixel format mixup, slanted or torn bars at a
+    /// wrong stride.
+    pub fn draw_test_pattern(&mut self) {
+        const BARS: [[u8; 3]; 8] = [
+            [255, 255, 255],
+            [255, 255, 0],
+            [0, 255, 255],
+            [0, 255, 0],
+            [255, 0, 255],
+            [255, 0, 0],
+            [0, 0, 255],
+            [0, 0, 0],
+        ];
+        let (width, height) = (self.width(), self.height());
+        for (i, &color) in BARS.iter().enumerate() {
+            let x = i * width / BARS.len();
+            let next_x = (i + 1) * width / BARS.len();
+            self.draw_filled_rect(x, 0, next_x - x, height, color);
+        }
+        self.draw_rect(0, 0, width, height, [255, 255, 255]);
+    }
+
+    /// Draws a `w` x `h` progress bar whose top left corner is at (x, y): an
+    /// outline in the text color, with `percent` of the inside filled in the
+    /// text color and the rest in the background color. Redraws the whole bar,
+    /// so calling it again updates it in place. `percent` is capped at 100.
+    pub fn draw_progress_bar(&mut self, x: usize, y: usize, w: usize, h: usize, percent: u8) {
+        if w < 2 || h < 2 {
+            return;
+        }
+        let (fg, bg) = (self.text_color, self.bg_color);
+        let inner_w = w - 2;
+        let filled = inner_w * percent.min(100) as usize / 100;
+        self.draw_rect(x, y, w, h, fg);
+        let (inner_x, inner_y) = (x.saturating_add(1), y.saturating_add(1));
+        self.draw_filled_rect(inner_x, inner_y, filled, h - 2, fg);
+        self.draw_filled_rect(inner_x.saturating_add(filled), inner_y, inner_w - filled, h - 2, bg);
+    }
+
+    /// Like [Self::write_pixel_bytes], but also skips negative coordinates.
+    fn plot(&mut self, x: isize, y: isize, color: [u8; 4]) {
+        if x >= 0 && y >= 0 {
+            self.write_pixel_bytes(x as usize, y as usize, color);
+        }
+    }
+
+    /// Fills row `y` from `x0` to `x1`, both included.
+    fn fill_span(&mut self, x0: isize, x1: isize, y: isize, color: [u8; 3]) {
+        if y < 0 || x1 < 0 {
+            return;
+        }
+        let x0 = x0.max(0);
+        self.draw_filled_rect(x0 as usize, y as usize, (x1 - x0 + 1) as usize, 1, color);
+    }
+}
+
+/// The color `step` of `steps` along a gradient from `start` to `end`; the
+/// first step is `start` and the last one `end`.
+fn lerp_color(start: [u8; 3], end: [u8; 3], step: usize, steps: usize) -> [u8; 3] {
+    let last = steps.saturating_sub(1).max(1) as i32;
+    let step = step as i32;
+    let mut color = [0; 3];
+    for (channel, (&from, &to)) in color.iter_mut().zip(start.iter().zip(&end)) {
+        *channel = (from as i32 + (to as i32 - from as i32) * step / last) as u8;
+    }
+    color
+}
+
+/// Twice the signed area of the triangle (a, b, p): positive if `p` is on one
+/// side of the line from `a` to `b`, negative on the other and 0 on it.
+fn edge(a: (usize, usize), b: (usize, usize), p: (usize, usize)) -> i64 {
+    let [ax, ay, bx, by, px, py] = [a.0, a.1, b.0, b.1, p.0, p.1].map(|n| n as i64);
+    (bx - ax) * (py - ay) - (by - ay) * (px - ax)
+}
+
+/// Cuts the line from `start` to `end` down to the part inside a `width` x
+/// `height` screen with the Cohen-Sutherland algorithm, or returns None if
+/// none of it is. The ends are moved along the line in i128, so any usize
+/// coordinates work; the ones returned are on screen.
+fn clip_line(
+    start: (usize, usize),
+    end: (usize, usize),
+    width: usize,
+    height: usize,
+) -> Option<((isize, isize), (isize, isize))> {
+    // Coordinates can't be negative, so only the right and bottom edges can
+    // be crossed.
+    const RIGHT: u8 = 1;
+    const BELOW: u8 = 2;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let (right, bottom) = (width as i128 - 1, height as i128 - 1);
+    let outside = |(x, y): (i128, i128)| (if x > right { RIGHT } else { 0 }) | (if y > bottom { BELOW } else { 0 });
+    let (mut a, mut b) = ((start.0 as i128, start.1 as i128), (end.0 as i128, end.1 as i128));
+    loop {
+        let (out_a, out_b) = (outside(a), outside(b));
+        if out_a | out_b == 0 {
+            return Some(((a.0 as isize, a.1 as isize), (b.0 as isize, b.1 as isize)));
+        }
+        if out_a & out_b != 0 {
+            return None;
+        }
+        // Move an end that's off screen onto the edge it's beyond. The other
+        // end is on the near side of that edge, so the divisor isn't zero.
+        let out = if out_a != 0 { out_a } else { out_b };
+        let moved = if out & RIGHT != 0 {
+            (right, a.1 + mul_div(b.1 - a.1, right - a.0, b.0 - a.0))
+        } else {
+            (a.0 + mul_div(b.0 - a.0, bottom - a.1, b.1 - a.1), bottom)
+        };
+        if out_a != 0 {
+            a = moved;
+        } else {
+            b = moved;
+        }
+    }
+}
+
+/// `a * b / c` rounded towards zero, for `|b| <= |c|` and `|a|`, `|b|` below
+/// 2^64, so the product fits in a u128.
+fn mul_div(a: i128, b: i128, c: i128) -> i128 {
+    let magnitude = (a.unsigned_abs() * b.unsigned_abs() / c.unsigned_abs()) as i128;
+    if (a < 0) ^ (b < 0) ^ (c < 0) { -magnitude } else { magnitude }
+}
+
+/// Walks the midpoint circle algorithm for radius `r`, calling `f` with each
+/// (x, y) offset of the first octant (from (r, 0) up to the diagonal).
+fn for_each_octant_point(r: usize, mut f: impl FnMut(isize, isize)) {
+    let (mut x, mut y) = (r as isize, 0);
+    let mut decision = 1 - x;
+    while x >= y {
+        f(x, y);
+        y += 1;
+        if decision < 0 {
+            decision += 2 * y + 1;
+        } else {
+            x -= 1;
+            decision += 2 * (y - x) + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::{get_char_raster, FrameBufferWriter};
+    use super::WrongPixelCount;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 60;
+    const RED: [u8; 3] = [255, 0, 0];
+    const WHITE: [u8; 3] = [255, 255, 255];
+
+    type Mock = MockFrameBuffer<{ WIDTH * HEIGHT * 4 }>;
+
+    fn mock() -> Mock {
+        Mock::new(PixelFormat::Rgb, WIDTH, HEIGHT)
+    }
+
+    /// Draws on a fresh screen and returns the pixels it left in `color`.
+    fn pixels_of(color: [u8; 3], draw: impl FnOnce(&mut FrameBufferWriter)) -> ([[bool; WIDTH]; HEIGHT], usize) {
+        let mut fb = mock();
+        draw(&mut fb.writer());
+        let mut pixels = [[false; WIDTH]; HEIGHT];
+        let mut count = 0;
+        for (y, row) in pixels.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = fb.pixel_at(x, y) == color;
+                count += *pixel as usize;
+            }
+        }
+        (pixels, count)
+    }
+
+    #[test]
+    fn rectangles() {
+        let (pixels, count) = pixels_of(RED, |w| w.draw_filled_rect(10, 5, 4, 3, RED));
+        assert_eq!(count, 12);
+        assert!(pixels[5][10] && pixels[7][13] && !pixels[8][13] && !pixels[5][14]);
+
+        let (pixels, count) = pixels_of(RED, |w| w.draw_rect(10, 5, 4, 3, RED));
+        assert_eq!(count, 10);
+        assert!(pixels[5][10] && pixels[7][13] && !pixels[6][11]);
+
+        // Clipped at the edges instead of wrapping onto the next row.
+        let (pixels, count) = pixels_of(RED, |w| w.draw_filled_rect(WIDTH - 2, 0, usize::MAX, 1, RED));
+        assert_eq!(count, 2);
+        assert!(!pixels[1][0]);
+        let (_, count) = pixels_of(RED, |w| w.draw_rect(WIDTH, HEIGHT, 5, 5, RED));
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn lines() {
+        let (pixels, count) = pixels_of(RED, |w| w.draw_line(0, 0, 9, 9, RED));
+        assert_eq!(count, 10);
+        assert!((0..10).all(|i| pixels[i][i]));
+        let (pixels, count) = pixels_of(RED, |w| w.draw_line(20, 3, 5, 3, RED));
+        assert_eq!(count, 16);
+        assert!(pixels[3][5] && pixels[3][20]);
+        let (pixels, count) = pixels_of(RED, |w| w.draw_line(3, 3, 3, 3, RED));
+        assert_eq!(count, 1);
+        assert!(pixels[3][3]);
+        // Off-screen parts are skipped.
+        let (_, count) = pixels_of(RED, |w| w.draw_line(70, 0, 90, 0, RED));
+        assert_eq!(count, 10);
+    }
+
+    #[test]
+    fn lines_are_clipped_before_they_are_walked() {
+        // Walking these unclipped would take ages or overflow.
+        let (pixels, count) = pixels_of(RED, |w| w.draw_line(0, 0, usize::MAX, 0, RED));
+        assert_eq!(count, WIDTH);
+        assert!(pixels[0].iter().all(|&lit| lit));
+        let (pixels, count) = pixels_of(RED, |w| w.draw_line(1 << 40, 1 << 40, 0, 0, RED));
+        assert_eq!(count, HEIGHT);
+        assert!((0..HEIGHT).all(|i| pixels[i][i]));
+        let (pixels, count) = pixels_of(RED, |w| w.draw_line(5, usize::MAX, 5, 10, RED));
+        assert_eq!(count, HEIGHT - 10);
+        assert!(pixels[10][5] && pixels[HEIGHT - 1][5]);
+        // Both ends off screen, with the line crossing a corner of it.
+        let (pixels, count) = pixels_of(RED, |w| w.draw_line(WIDTH + 10, 0, 0, WIDTH + 10, RED));
+        assert!(count > 0 && pixels[HEIGHT - 1][WIDTH + 10 - (HEIGHT - 1)]);
+        // Beyond the screen the whole way.
+        let (_, count) = pixels_of(RED, |w| w.draw_line(WIDTH, 0, usize::MAX, usize::MAX, RED));
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn circles() {
+        let (pixels, _) = pixels_of(RED, |w| w.draw_circle(30, 30, 10, RED));
+        assert!(pixels[20][30] && pixels[40][30] && pixels[30][20] && pixels[30][40]);
+        assert!(!pixels[30][30]);
+        let (pixels, _) = pixels_of(RED, |w| w.draw_filled_circle(30, 30, 10, RED));
+        assert!(pixels[30][30] && pixels[20][30] && pixels[30][40]);
+        assert!(!pixels[20][20] && !pixels[30][41]);
+        // Partly off the top left corner is fine.
+        let (pixels, _) = pixels_of(RED, |w| w.draw_filled_circle(0, 0, 5, RED));
+        assert!(pixels[0][0] && pixels[5][0] && !pixels[5][5]);
+    }
+
+    #[test]
+    fn blit_copies_rows() {
+        let image = [RED, WHITE, WHITE, RED, [0, 0, 255], RED];
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        assert_eq!(writer.blit(5, 5, 3, 2, &image), Ok(()));
+        assert_eq!(writer.blit(0, 0, 3, 3, &image), Err(WrongPixelCount { expected: 9, actual: 6 }));
+        // Clipped at the right edge rather than wrapped.
+        assert_eq!(writer.blit(WIDTH - 1, 20, 3, 2, &image), Ok(()));
+        assert_eq!([fb.pixel_at(5, 5), fb.pixel_at(7, 5), fb.pixel_at(6, 6)], [RED, WHITE, [0, 0, 255]]);
+        assert_eq!(fb.pixel_at(0, 0), [0; 3]);
+        assert_eq!((fb.pixel_at(WIDTH - 1, 20), fb.pixel_at(0, 21)), (RED, [0; 3]));
+    }
+
+    #[test]
+    fn fills_the_screen() {
+        let (_, count) = pixels_of(RED, |w| w.fill_screen(RED));
+        assert_eq!(count, WIDTH * HEIGHT);
+    }
+
+    #[test]
+    fn test_pattern() {
+        let mut fb = mock();
+        fb.writer().draw_test_pattern();
+        let bar_width = WIDTH / 8;
+        assert_eq!(fb.pixel_at(bar_width + 2, HEIGHT / 2), [255, 255, 0]);
+        assert_eq!(fb.pixel_at(6 * bar_width + 2, HEIGHT / 2), [0, 0, 255]);
+        assert_eq!(fb.pixel_at(7 * bar_width + 2, HEIGHT / 2), [0; 3]);
+        for (x, y) in [(WIDTH - 1, HEIGHT / 2), (WIDTH / 2, HEIGHT - 1), (WIDTH - 1, HEIGHT - 1)] {
+            assert_eq!(fb.pixel_at(x, y), WHITE, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn progress_bar() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.set_text_color(RED);
+        writer.draw_progress_bar(0, 0, 22, 5, 50);
+        // 50% of the 20 inner columns
+        assert_eq!([fb.pixel_at(1, 2), fb.pixel_at(10, 2), fb.pixel_at(11, 2)], [RED, RED, [0; 3]]);
+        assert_eq!((fb.pixel_at(21, 2), fb.pixel_at(15, 4)), (RED, RED));
+
+        let mut writer = fb.writer();
+        writer.set_text_color(RED);
+        writer.draw_progress_bar(0, 0, 22, 5, 250);
+        assert_eq!(fb.pixel_at(20, 2), RED);
+
+        // Near the edges the bar is clipped.
+        let mut writer = fb.writer();
+        writer.draw_progress_bar(usize::MAX, usize::MAX, 10, 10, 50);
+        writer.draw_progress_bar(WIDTH - 3, 0, 10 * WIDTH, 5, 100);
+        writer.draw_progress_bar(0, 0, 1, 1, 100);
+    }
+
+    #[test]
+    fn gradients() {
+        let mut fb = mock();
+        fb.writer().draw_gradient_rect(0, 0, 11, 3, [0, 0, 0], [100, 200, 250], false);
+        assert_eq!([fb.pixel_at(0, 1), fb.pixel_at(5, 1), fb.pixel_at(10, 1)], [[0; 3], [50, 100, 125], [100, 200, 250]]);
+        assert_eq!(fb.pixel_at(11, 1), [0; 3]);
+
+        let mut fb = mock();
+        fb.writer().draw_gradient_rect(0, 0, 3, 5, RED, [0, 0, 255], true);
+        assert_eq!([fb.pixel_at(1, 0), fb.pixel_at(1, 4)], [RED, [0, 0, 255]]);
+        assert_eq!(fb.pixel_at(1, 2), [128, 0, 127]);
+    }
+
+    #[test]
+    fn copy_rect_handles_overlap() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        for i in 0..10 {
+            writer.set_pixel(i, 0, [i as u8 * 10, 0, 0]);
+            writer.set_pixel(0, 10 + i, [0, i as u8 * 10, 0]);
+        }
+        writer.copy_rect((0, 0), (2, 0), 10, 1);
+        writer.copy_rect((0, 11), (0, 10), 1, 9);
+        assert_eq!(core::array::from_fn::<u8, 10, _>(|i| fb.pixel_at(2 + i, 0)[0]), core::array::from_fn(|i| i as u8 * 10));
+        assert_eq!(core::array::from_fn::<u8, 9, _>(|i| fb.pixel_at(0, 10 + i)[1]), core::array::from_fn(|i| i as u8 * 10 + 10));
+
+        // Blocks that would end up off-screen are clipped.
+        let mut writer = fb.writer();
+        writer.set_pixel(WIDTH - 1, HEIGHT - 1, RED);
+        writer.copy_rect((WIDTH - 1, HEIGHT - 1), (0, 0), usize::MAX, usize::MAX);
+        writer.copy_rect((0, 0), (WIDTH, 0), 5, 5);
+        assert_eq!(fb.pixel_at(0, 0), RED);
+        assert_eq!(fb.pixel_at(1, 0), [0; 3]);
+    }
+
+    #[test]
+    fn triangles() {
+        let corners = ((0, 0), (10, 0), (0, 10));
+        let (pixels, _) = pixels_of(RED, |w| w.fill_triangle(corners.0, corners.1, corners.2, RED));
+        assert!(pixels[2][2] && pixels[0][10] && pixels[10][0]);
+        assert!(!pixels[8][8]);
+        // The corners' order doesn't matter.
+        let (reversed, _) = pixels_of(RED, |w| w.fill_triangle(corners.2, corners.1, corners.0, RED));
+        assert_eq!(pixels, reversed);
+        let (outline, _) = pixels_of(RED, |w| w.draw_triangle(corners.0, corners.1, corners.2, RED));
+        assert!(outline[0][5] && outline[5][5] && !outline[2][2]);
+        let (_, count) = pixels_of(RED, |w| w.fill_triangle((0, 0), (5, 0), (9, 0), RED));
+        assert_eq!(count, 10);
+    }
+
+    #[test]
+    fn blends_pixels() {
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.fill_screen(WHITE);
+        assert!(writer.blend_pixel(0, 0, [0; 3], 128));
+        assert!(writer.blend_pixel(1, 0, RED, 255));
+        assert!(writer.blend_pixel(2, 0, RED, 0));
+        assert!(!writer.blend_pixel(WIDTH, 0, RED, 255));
+        assert_eq!([fb.pixel_at(0, 0), fb.pixel_at(1, 0), fb.pixel_at(2, 0)], [[127; 3], RED, WHITE]);
+    }
+
+    #[test]
+    fn scaled_chars() {
+        let raster = get_char_raster('A').unwrap();
+        let covered = raster.raster().iter().flat_map(|row| row.iter()).filter(|&&i| i > 0).count();
+        let (pixels, _) = pixels_of([0; 3], |w| w.draw_char_scaled(0, 0, 'A', 3, WHITE));
+        let lit = pixels.iter().flatten().filter(|&&black| !black).count();
+        assert_eq!(lit, 9 * covered);
+        let (width, height) = (3 * raster.width(), 3 * raster.height());
+        let mut outside = (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).filter(|&(x, y)| x >= width || y >= height);
+        assert!(outside.all(|(x, y)| pixels[y][x]));
+        // Clipped at the edge
+        pixels_of(WHITE, |w| w.draw_char_scaled(WIDTH - 5, HEIGHT - 5, 'A', 50, WHITE));
+    }
+
+    #[test]
+    fn rounded_rects() {
+        let (pixels, _) = pixels_of(RED, |w| w.draw_rounded_rect(10, 10, 30, 20, 6, RED));
+        assert!(!pixels[10][10] && pixels[12][12] && pixels[10][25] && pixels[20][10]);
+        assert!(!pixels[29][39] && pixels[29][25] && !pixels[30][25]);
+        // Without a radius it's a plain rectangle.
+        let (_, count) = pixels_of(RED, |w| w.draw_rounded_rect(0, 0, 4, 3, 0, RED));
+        assert_eq!(count, 12);
+        pixels_of(RED, |w| w.draw_rounded_rect(WIDTH - 2, HEIGHT - 2, usize::MAX, usize::MAX, 100, RED));
+    }
+
+    #[test]
+    fn rgba_images() {
+        let rgba = [255, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 128];
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.fill_screen([0, 0, 255]);
+        assert_eq!(writer.draw_image_rgba(0, 0, 3, 1, &rgba), Ok(()));
+        assert_eq!(writer.draw_image_rgba(0, 0, 2, 2, &rgba), Err(WrongPixelCount { expected: 16, actual: 12 }));
+        assert_eq!([fb.pixel_at(0, 0), fb.pixel_at(1, 0), fb.pixel_at(2, 0)], [RED, [0, 0, 255], [0, 0, 127]]);
+    }
+}
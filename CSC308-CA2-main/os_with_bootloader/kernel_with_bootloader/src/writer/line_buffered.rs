@@ -0,0 +1,136 @@
+use core::fmt::{self, Write};
+
+/// Default size of a [LineBuffered] buffer in bytes.
+pub const LINE_BUFFER_LEN: usize = 128;
+
+/// Collects text in an `N` byte buffer on the stack and passes it on to the
+/// inner writer a line at a time, or sooner if the buffer fills up. Saves
+/// drawing, and taking the lock of a [super::SyncWriter], for each of the many
+/// small pieces `write!` produces. Whatever is left is flushed on drop.
+///
+/// ```no_run
+/// use core::fmt::Write;
+/// use kernel_with_bootloader::writer::{LineBuffered, SyncWriter};
+///
+/// fn report(writer: &SyncWriter) {
+///     let mut out = LineBuffered::<_>::new(writer);
+///     // one lock for the whole line instead of one per argument
+///     writeln!(out, "{} of {} done", 3, 7).unwrap();
+/// }
+/// ```
+pub struct LineBuffered<W: Write, const N: usize = LINE_BUFFER_LEN> {
+    inner: W,
+    buffer: [u8; N],
+    len: usize,
+}
+
+impl<W: Write, const N: usize> LineBuffered<W, N> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, buffer: [0; N], len: 0 }
+    }
+
+    /// Passes the buffered text on to the inner writer.
+    pub fn flush(&mut self) -> fmt::Result {
+        if self.len == 0 {
+            return Ok(());
+        }
+        // Only whole chars are ever buffered, so this is valid UTF-8.
+        let text = core::str::from_utf8(&self.buffer[..self.len]).map_err(|_| fmt::Error)?;
+        self.len = 0;
+        self.inner.write_str(text)
+    }
+}
+
+impl<W: Write, const N: usize> Write for LineBuffered<W, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let mut encoded = [0; 4];
+            let bytes = c.encode_utf8(&mut encoded).as_bytes();
+            if self.len + bytes.len() > N {
+                self.flush()?;
+            }
+            if bytes.len() > N {
+                // Doesn't fit even an empty buffer.
+                self.inner.write_char(c)?;
+                continue;
+            }
+            self.buffer[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+            if c == '\n' {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write, const N: usize> Drop for LineBuffered<W, N> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::fmt::{self, Write};
+    use std::{string::String, vec::Vec};
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::{SyncWriter, TextPosition};
+    use super::LineBuffered;
+
+    /// Keeps every piece of text passed on, one entry per call.
+    #[derive(Default)]
+    struct Pieces(Vec<String>);
+
+    impl Write for &mut Pieces {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.push(String::from(s));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn passes_on_whole_lines() {
+        let mut pieces = Pieces::default();
+        let mut out = LineBuffered::<_>::new(&mut pieces);
+        write!(out, "{} of {} done\nand ", 3, 7).unwrap();
+        out.write_str("more").unwrap();
+        drop(out);
+        assert_eq!(pieces.0, ["3 of 7 done\n", "and more"]);
+    }
+
+    #[test]
+    fn flushes_when_full() {
+        let mut pieces = Pieces::default();
+        let mut out = LineBuffered::<_, 4>::new(&mut pieces);
+        out.write_str("abcdeé").unwrap();
+        out.flush().unwrap();
+        out.flush().unwrap();
+        drop(out);
+        assert_eq!(pieces.0, ["abcd", "eé"]);
+
+        // Chars that can't fit the buffer at all go straight through.
+        let mut pieces = Pieces::default();
+        let mut out = LineBuffered::<_, 1>::new(&mut pieces);
+        out.write_str("aé").unwrap();
+        drop(out);
+        assert_eq!(pieces.0, ["a", "é"]);
+    }
+
+    #[test]
+    fn feeds_a_shared_writer() {
+        let mut fb = MockFrameBuffer::<{ 80 * 40 * 4 }>::new(PixelFormat::Rgb, 80, 40);
+        let writer = SyncWriter::new(fb.writer());
+        let mut out = LineBuffered::<_>::new(&writer);
+        write!(out, "{}{}", 1, 2).unwrap();
+        // Nothing drawn before the line is done
+        assert_eq!(writer.lock().char_at(0, 0), Some(' '));
+        drop(out);
+        assert_eq!(writer.lock().find("12"), Some(TextPosition::Screen { col: 0, row: 0 }));
+    }
+}
@@ -0,0 +1,103 @@
+use bootloader_api::info::FrameBufferInfo;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use spin::{Mutex, MutexGuard, Once};
+
+use super::{FrameBufferWriter, SyncWriter, TextBuffer};
+
+static LOGGER: Once<LockedWriter> = Once::new();
+
+/// The text of [LOGGER], kept out of the stack like that of
+/// [super::WRITER].
+static LOGGER_TEXT: Mutex<TextBuffer> = Mutex::new(TextBuffer::new());
+
+/// A [SyncWriter] over the bootloader's framebuffer, usable as the backend of
+/// the `log` crate's macros.
+pub type LockedWriter = SyncWriter<'static>;
+
+/// Text color of a log record of the given level.
+fn level_color(level: Level) -> [u8; 3] {
+    match level {
+        Level::Error => [255, 0, 0],
+        Level::Warn => [255, 255, 0],
+        Level::Info => [255, 255, 255],
+        Level::Debug => [0, 255, 255],
+        Level::Trace => [127, 127, 127],
+    }
+}
+
+impl Log for LockedWriter {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.lock().write_colored_fmt(
+            level_color(record.level()),
+            format_args!("[{:5}] {}\n", record.level(), record.args()),
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+/// Creates a writer over the framebuffer and installs it as the global logger,
+/// so `log::info!` and friends print to the screen.
+pub fn init_logger(
+    framebuffer: &'static mut [u8],
+    info: FrameBufferInfo,
+) -> Result<(), SetLoggerError> {
+    let logger = LOGGER.call_once(|| {
+        let text = MutexGuard::leak(LOGGER_TEXT.lock());
+        LockedWriter::new(FrameBufferWriter::new_in(framebuffer, info, text))
+    });
+    log::set_logger(logger)?;
+    log::set_max_level(LevelFilter::Trace);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+    use log::{Level, Log, Record};
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::{FrameBufferWriter, TextBuffer, TextPosition};
+    use super::{init_logger, level_color, LockedWriter, LOGGER};
+
+    const WIDTH: usize = 200;
+    const HEIGHT: usize = 60;
+
+    fn info() -> FrameBufferInfo {
+        MockFrameBuffer::<{ WIDTH * HEIGHT * 4 }>::new(PixelFormat::Rgb, WIDTH, HEIGHT).info()
+    }
+
+    #[test]
+    fn records_are_colored_by_level() {
+        let framebuffer = Box::leak(Box::new([0; WIDTH * HEIGHT * 4]));
+        let text = Box::leak(Box::new(TextBuffer::new()));
+        let logger = LockedWriter::new(FrameBufferWriter::new_in(framebuffer, info(), text));
+        logger.log(&Record::builder().level(Level::Error).args(format_args!("disk {} failed", 0)).build());
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("ok")).build());
+        let writer = logger.lock();
+        assert_eq!(writer.find("[ERROR] disk 0 failed"), Some(TextPosition::Screen { col: 0, row: 0 }));
+        assert_eq!(writer.find("[INFO ] ok"), Some(TextPosition::Screen { col: 0, row: 1 }));
+        assert_eq!(writer.cells[0][0].fg, [255, 0, 0]);
+        assert_eq!(writer.cells[1][0].fg, level_color(Level::Info));
+        // The writer's own color is left alone.
+        assert_eq!(writer.text_color, super::super::DEFAULT_TEXT_COLOR);
+    }
+
+    #[test]
+    fn log_macros_print_to_the_screen() {
+        let framebuffer = Box::leak(Box::new([0; WIDTH * HEIGHT * 4]));
+        init_logger(framebuffer, info()).unwrap();
+        log::warn!("low on {}", "memory");
+        let writer = LOGGER.get().unwrap().lock();
+        assert_eq!(writer.find("[WARN ] low on memory"), Some(TextPosition::Screen { col: 0, row: 0 }));
+        assert_eq!(writer.cells[0][0].fg, level_color(Level::Warn));
+    }
+}
@@ -0,0 +1,131 @@
+//! An in-memory framebuffer for exercising the writer without real hardware.
+//! Only built with the `mock` feature and for the crate's own tests.
+
+use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+
+use super::{Color, FrameBufferWriter, TextBuffer};
+
+/// A framebuffer backed by a fixed `N` byte array. Rendering into it is fully
+/// deterministic, so the result can be checked pixel by pixel.
+///
+/// ```
+/// use core::fmt::Write;
+/// use bootloader_api::info::PixelFormat;
+/// use kernel_with_bootloader::writer::mock::MockFrameBuffer;
+///
+/// let mut fb = MockFrameBuffer::<{ 64 * 32 * 4 }>::new(PixelFormat::Rgb, 64, 32);
+/// write!(fb.writer(), "Hi").unwrap();
+/// assert!(!fb.is_blank());
+/// ```
+pub struct MockFrameBuffer<const N: usize> {
+    bytes: [u8; N],
+    info: FrameBufferInfo,
+    text: TextBuffer,
+}
+
+impl<const N: usize> MockFrameBuffer<N> {
+    /// Creates a blank `width` x `height` framebuffer without row padding.
+    ///
+    /// Panics if the format isn't Rgb, Bgr or U8, or if `N` is too small to
+    /// hold every pixel.
+    pub fn new(pixel_format: PixelFormat, width: usize, height: usize) -> Self {
+        let bytes_per_pixel = match pixel_format {
+            PixelFormat::Rgb | PixelFormat::Bgr => 4,
+            PixelFormat::U8 => 1,
+            other => panic!("mock framebuffer doesn't support {:?}", other),
+        };
+        Self::with_info(FrameBufferInfo {
+            byte_len: width * height * bytes_per_pixel,
+            width,
+            height,
+            pixel_format,
+            bytes_per_pixel,
+            stride: width,
+        })
+    }
+
+    /// Creates a blank framebuffer with any layout, e.g. padded rows or
+    /// 16-bit pixels. `info.byte_len` bytes of it are handed to the writer.
+    ///
+    /// Panics if `N` is smaller than `info.byte_len`.
+    pub fn with_info(info: FrameBufferInfo) -> Self {
+        assert!(info.byte_len <= N, "{} bytes don't fit a {} byte framebuffer", N, info.byte_len);
+        Self { bytes: [0; N], text: TextBuffer::new(), info }
+    }
+
+    pub fn info(&self) -> FrameBufferInfo {
+        self.info
+    }
+
+    /// The raw framebuffer bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.info.byte_len]
+    }
+
+    /// The framebuffer bytes and the text buffer, for building a writer with
+    /// one of the other constructors, e.g. [FrameBufferWriter::with_viewport_in].
+    pub fn parts(&mut self) -> (&mut [u8], &mut TextBuffer) {
+        (&mut self.bytes[..self.info.byte_len], &mut self.text)
+    }
+
+    /// Creates a writer drawing into this framebuffer. Like
+    /// [FrameBufferWriter::new_in] it starts by clearing the screen.
+    pub fn writer(&mut self) -> FrameBufferWriter<'_> {
+        FrameBufferWriter::new_in(&mut self.bytes[..self.info.byte_len], self.info, &mut self.text)
+    }
+
+    /// Like [Self::writer], but drawing into `back_buffer` until presented,
+    /// see [FrameBufferWriter::with_back_buffer_in].
+    pub fn writer_with_back_buffer<'a>(&'a mut self, back_buffer: &'a mut [u8]) -> FrameBufferWriter<'a> {
+        let info = self.info;
+        FrameBufferWriter::with_back_buffer_in(&mut self.bytes[..info.byte_len], back_buffer, info, &mut self.text)
+    }
+
+    /// Returns the color of the pixel at (x, y) of the framebuffer as
+    /// `[r, g, b]`, read back like [Color::from_bytes] does. U8 pixels come
+    /// back as the same gray level in every channel.
+    ///
+    /// Panics if (x, y) is outside the framebuffer.
+    pub fn pixel_at(&self, x: usize, y: usize) -> [u8; 3] {
+        assert!(x < self.info.width && y < self.info.height, "({}, {}) is off screen", x, y);
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let offset = (y * self.info.stride + x) * bytes_per_pixel;
+        let mut bytes = [0; 4];
+        bytes[..bytes_per_pixel].copy_from_slice(&self.bytes[offset..offset + bytes_per_pixel]);
+        Color::from_bytes(bytes, self.info.pixel_format, bytes_per_pixel).into()
+    }
+
+    /// Whether every pixel is black, which is what a fresh writer clears to.
+    pub fn is_blank(&self) -> bool {
+        (0..self.info.height)
+            .all(|y| (0..self.info.width).all(|x| self.pixel_at(x, y) == [0, 0, 0]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::MockFrameBuffer;
+
+    #[test]
+    fn renders_in_every_format() {
+        for format in [PixelFormat::Rgb, PixelFormat::Bgr, PixelFormat::U8] {
+            let mut fb = MockFrameBuffer::<{ 64 * 32 * 4 }>::new(format, 64, 32);
+            assert!(fb.is_blank());
+            let mut writer = fb.writer();
+            writer.set_text_color([255, 0, 0]);
+            write!(writer, "Hi").unwrap();
+            assert!(!fb.is_blank(), "nothing drawn in {:?}", format);
+            let mut pixels = (0..32).flat_map(|y| (0..64).map(move |x| (x, y))).map(|(x, y)| fb.pixel_at(x, y));
+            let expected = |[r, g, b]: [u8; 3]| match format {
+                // Red text reads back red whatever the byte order.
+                PixelFormat::U8 => r == g && g == b,
+                _ => g == 0 && b == 0,
+            };
+            assert!(pixels.all(expected), "{:?}", format);
+        }
+    }
+}
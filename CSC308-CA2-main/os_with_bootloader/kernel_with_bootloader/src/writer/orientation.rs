@@ -0,0 +1,111 @@
+use super::FrameBufferWriter;
+
+/// How the screen is turned relative to the framebuffer, for panels that
+/// are mounted sideways or upside down. The rotation is clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl FrameBufferWriter<'_> {
+    /// Whether width and height are swapped relative to the framebuffer.
+    pub(super) fn is_sideways(&self) -> bool {
+        matches!(self.orientation, Orientation::Rotate90 | Orientation::Rotate270)
+    }
+
+    /// Maps on-screen pixel (x, y) to where it is in the framebuffer.
+    pub(super) fn physical(&self, x: usize, y: usize) -> (usize, usize) {
+        let (width, height) = (self.info.width, self.info.height);
+        match self.orientation {
+            Orientation::Normal => (x, y),
+            Orientation::Rotate90 => (width - 1 - y, x),
+            Orientation::Rotate180 => (width - 1 - x, height - 1 - y),
+            Orientation::Rotate270 => (y, height - 1 - x),
+        }
+    }
+
+    /// Copies the `w` x `h` block of on-screen pixels at (src_x, src_y) to
+    /// (dst_x, dst_y) one pixel at a time, so it works in any orientation.
+    /// The blocks may overlap.
+    pub(super) fn move_rect(&mut self, src_x: usize, src_y: usize, dst_x: usize, dst_y: usize, w: usize, h: usize) {
+        let copy = |writer: &mut Self, x: usize, y: usize| {
+            let color = writer.read_pixel_bytes(src_x + x, src_y + y).unwrap_or([0; 4]);
+            writer.write_pixel_bytes(dst_x + x, dst_y + y, color);
+        };
+        // Walk away from the destination so no pixel is overwritten before
+        // it's copied.
+        if (dst_y, dst_x) <= (src_y, src_x) {
+            for y in 0..h {
+                for x in 0..w {
+                    copy(self, x, y);
+                }
+            }
+        } else {
+            for y in (0..h).rev() {
+                for x in (0..w).rev() {
+                    copy(self, x, y);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::FrameBufferWriter;
+    use super::Orientation;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+    const RED: [u8; 3] = [255, 0, 0];
+
+    type Mock = MockFrameBuffer<{ WIDTH * HEIGHT * 4 }>;
+
+    fn turned(orientation: Orientation, draw: impl FnOnce(&mut FrameBufferWriter)) -> Mock {
+        let mut fb = Mock::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let info = fb.info();
+        let (bytes, text) = fb.parts();
+        draw(&mut FrameBufferWriter::with_orientation_in(bytes, info, orientation, text));
+        fb
+    }
+
+    #[test]
+    fn maps_pixels_to_the_framebuffer() {
+        let cases = [
+            (Orientation::Normal, (WIDTH, HEIGHT), (0, 0)),
+            (Orientation::Rotate90, (HEIGHT, WIDTH), (WIDTH - 1, 0)),
+            (Orientation::Rotate180, (WIDTH, HEIGHT), (WIDTH - 1, HEIGHT - 1)),
+            (Orientation::Rotate270, (HEIGHT, WIDTH), (0, HEIGHT - 1)),
+        ];
+        for (orientation, dimensions, (px, py)) in cases {
+            let fb = turned(orientation, |writer| {
+                assert_eq!(writer.dimensions(), dimensions, "{:?}", orientation);
+                assert!(writer.set_pixel(0, 0, RED));
+                assert!(!writer.set_pixel(dimensions.0, 0, RED));
+            });
+            assert_eq!(fb.pixel_at(px, py), RED, "{:?}", orientation);
+        }
+    }
+
+    #[test]
+    fn text_scrolls_in_any_orientation() {
+        for orientation in [Orientation::Rotate90, Orientation::Rotate180, Orientation::Rotate270] {
+            let fb = turned(orientation, |writer| {
+                for _ in 0..6 {
+                    core::fmt::Write::write_str(writer, "ab\n").unwrap();
+                }
+                writer.draw_filled_rect(0, 0, 2, 1, RED);
+                writer.copy_rect((0, 0), (5, 5), 2, 1);
+                assert_eq!(writer.char_at(0, 0), Some('a'));
+            });
+            let red = (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y))).filter(|&(x, y)| fb.pixel_at(x, y) == RED);
+            assert_eq!(red.count(), 4, "{:?}", orientation);
+        }
+    }
+}
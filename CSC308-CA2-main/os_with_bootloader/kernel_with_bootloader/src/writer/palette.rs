@@ -0,0 +1,70 @@
+/// The standard xterm 256-color palette: the 16 ANSI colors, a 6x6x6 color
+/// cube and a 24 step grayscale ramp.
+pub const PALETTE_256: [[u8; 3]; 256] = build_palette();
+
+/// The 8 standard and 8 bright ANSI colors, as used by xterm.
+const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+/// Channel values of the 6 steps along each axis of the color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+const fn build_palette() -> [[u8; 3]; 256] {
+    let mut palette = [[0; 3]; 256];
+    let mut i = 0;
+    while i < 16 {
+        palette[i] = ANSI_COLORS[i];
+        i += 1;
+    }
+    // 16..=231: the color cube, blue varying fastest
+    while i < 232 {
+        let n = i - 16;
+        palette[i] = [CUBE_LEVELS[n / 36], CUBE_LEVELS[n / 6 % 6], CUBE_LEVELS[n % 6]];
+        i += 1;
+    }
+    // 232..=255: grays from 8 to 238
+    while i < 256 {
+        let level = (8 + (i - 232) * 10) as u8;
+        palette[i] = [level, level, level];
+        i += 1;
+    }
+    palette
+}
+
+/// Returns color `n` of the xterm 256-color palette.
+pub fn palette_color(n: u8) -> [u8; 3] {
+    PALETTE_256[n as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::palette_color;
+
+    #[test]
+    fn matches_xterm() {
+        assert_eq!(palette_color(1), [205, 0, 0]);
+        assert_eq!(palette_color(16), [0, 0, 0]);
+        assert_eq!(palette_color(21), [0, 0, 255]);
+        assert_eq!(palette_color(196), [255, 0, 0]);
+        assert_eq!(palette_color(208), [255, 135, 0]);
+        assert_eq!(palette_color(231), [255, 255, 255]);
+        assert_eq!(palette_color(232), [8, 8, 8]);
+        assert_eq!(palette_color(255), [238, 238, 238]);
+    }
+}
@@ -0,0 +1,60 @@
+use core::fmt;
+
+use super::{Color, FrameBufferWriter};
+
+impl FrameBufferWriter<'_> {
+    /// Writes the screen to `out` as a plain (P3) PPM image, one text line
+    /// per pixel row, e.g. to dump it over a serial port and look at it on
+    /// another machine. With a back buffer, that's what gets written, drawn
+    /// but not yet presented parts included.
+    pub fn write_ppm<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        let (width, height) = (self.width(), self.height());
+        let (format, bytes_per_pixel) = (self.info.pixel_format, self.info.bytes_per_pixel);
+        write!(out, "P3\n{} {}\n255\n", width, height)?;
+        for y in 0..height {
+            for x in 0..width {
+                let bytes = self.read_pixel_bytes(x, y).unwrap_or_default();
+                let Color(r, g, b) = Color::from_bytes(bytes, format, bytes_per_pixel);
+                if x > 0 {
+                    out.write_char(' ')?;
+                }
+                write!(out, "{} {} {}", r, g, b)?;
+            }
+            out.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+
+    #[test]
+    fn writes_every_pixel() {
+        let mut fb = MockFrameBuffer::<{ 3 * 2 }>::new(PixelFormat::U8, 3, 2);
+        let mut writer = fb.writer();
+        writer.set_pixel(0, 0, [255, 255, 255]);
+        writer.set_pixel(2, 1, [255, 255, 255]);
+        let mut out = String::new();
+        writer.write_ppm(&mut out).unwrap();
+        assert_eq!(out, "P3\n3 2\n255\n255 255 255 0 0 0 0 0 0\n0 0 0 0 0 0 255 255 255\n");
+    }
+
+    #[test]
+    fn writes_the_back_buffer() {
+        let mut back = [0; 2 * 4];
+        let mut fb = MockFrameBuffer::<{ 2 * 4 }>::new(PixelFormat::Bgr, 2, 1);
+        let mut writer = fb.writer_with_back_buffer(&mut back);
+        writer.set_pixel(1, 0, [1, 2, 3]);
+        let mut out = String::new();
+        writer.write_ppm(&mut out).unwrap();
+        assert_eq!(out, "P3\n2 1\n255\n0 0 0 1 2 3\n");
+    }
+}
@@ -0,0 +1,291 @@
+use super::{FrameBufferWriter, Glyph, BACKUP_CHAR};
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x02;
+const PSF1_MODE_HAS_SEQUENCES: u8 = 0x04;
+const PSF1_HEADER_LEN: usize = 4;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HAS_TABLE: u32 = 0x01;
+const PSF2_HEADER_LEN: usize = 32;
+
+/// Marks "no glyph" in [PsfFont]'s ASCII lookup table.
+const NO_GLYPH: u16 = u16::MAX;
+
+/// Why a PSF font couldn't be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsfError {
+    /// Neither a PSF1 nor a PSF2 file.
+    BadMagic,
+    /// The file ends before the header or the glyphs do.
+    Truncated,
+    /// The header describes empty glyphs or glyphs bigger than their size
+    /// in bytes.
+    BadHeader,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    Psf1,
+    Psf2,
+}
+
+/// A PC Screen Font (PSF1 or PSF2), as used by the Linux console. Borrows
+/// the font file, e.g. one embedded with `include_bytes!`.
+#[derive(Clone, Copy)]
+pub struct PsfFont<'a> {
+    version: Version,
+    glyphs: &'a [u8],
+    /// The Unicode table mapping glyphs to the chars they show, if any.
+    table: Option<&'a [u8]>,
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    width: usize,
+    height: usize,
+    /// The glyph of each ASCII char, looked up in the table once up front.
+    ascii: [u16; 128],
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+impl<'a> PsfFont<'a> {
+    /// Parses a PSF1 or PSF2 font file.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, PsfError> {
+        let (version, header_len, glyph_count, bytes_per_glyph, width, height, has_table) =
+            if bytes.starts_with(&PSF1_MAGIC) {
+                if bytes.len() < PSF1_HEADER_LEN {
+                    return Err(PsfError::Truncated);
+                }
+                let (mode, height) = (bytes[2], bytes[3] as usize);
+                let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+                let has_table = mode & (PSF1_MODE_HAS_TABLE | PSF1_MODE_HAS_SEQUENCES) != 0;
+                (Version::Psf1, PSF1_HEADER_LEN, glyph_count, height, 8, height, has_table)
+            } else if bytes.starts_with(&PSF2_MAGIC) {
+                if bytes.len() < PSF2_HEADER_LEN {
+                    return Err(PsfError::Truncated);
+                }
+                let field = |index: usize| read_u32(bytes, 4 * index) as usize;
+                let has_table = field(3) as u32 & PSF2_HAS_TABLE != 0;
+                (Version::Psf2, field(2), field(4), field(5), field(7), field(6), has_table)
+            } else {
+                return Err(PsfError::BadMagic);
+            };
+        if glyph_count == 0 || width == 0 || height == 0 || width.div_ceil(8) * height > bytes_per_glyph {
+            return Err(PsfError::BadHeader);
+        }
+        let glyphs_end = glyph_count
+            .checked_mul(bytes_per_glyph)
+            .and_then(|len| len.checked_add(header_len))
+            .ok_or(PsfError::Truncated)?;
+        let glyphs = bytes.get(header_len..glyphs_end).ok_or(PsfError::Truncated)?;
+        let mut font = Self {
+            version,
+            glyphs,
+            table: has_table.then(|| &bytes[glyphs_end..]),
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+            ascii: [NO_GLYPH; 128],
+        };
+        if font.table.is_some() {
+            let mut ascii = [NO_GLYPH; 128];
+            font.for_each_mapping(|c, glyph| {
+                if let Some(slot) = ascii.get_mut(c as usize)
+                    && *slot == NO_GLYPH
+                {
+                    *slot = glyph as u16;
+                }
+                false
+            });
+            font.ascii = ascii;
+        }
+        Ok(font)
+    }
+
+    /// Width of each glyph in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of each glyph in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    /// Whether the font has a glyph for `c`.
+    pub fn contains(&self, c: char) -> bool {
+        self.glyph_index(c).is_some()
+    }
+
+    /// The glyph showing `c`, or the one for [BACKUP_CHAR] (or else the first
+    /// glyph) if the font doesn't have it.
+    pub(super) fn glyph(&self, c: char) -> Glyph<'a> {
+        let index = self.glyph_index(c).or_else(|| self.glyph_index(BACKUP_CHAR)).unwrap_or(0);
+        let start = index * self.bytes_per_glyph;
+        Glyph::Bitmap {
+            bits: &self.glyphs[start..start + self.bytes_per_glyph],
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Without a Unicode table glyphs are indexed by code point.
+    fn glyph_index(&self, c: char) -> Option<usize> {
+        if self.table.is_none() {
+            return Some(c as usize).filter(|&index| index < self.glyph_count);
+        }
+        if let Some(&glyph) = self.ascii.get(c as usize) {
+            return (glyph != NO_GLYPH).then_some(glyph as usize);
+        }
+        let mut found = None;
+        self.for_each_mapping(|mapped, glyph| {
+            found = (mapped == c).then_some(glyph);
+            found.is_some()
+        });
+        found
+    }
+
+    /// Calls `f` with every (char, glyph index) pair in the Unicode table
+    /// until it returns true. Multi-char sequences are skipped.
+    fn for_each_mapping(&self, mut f: impl FnMut(char, usize) -> bool) {
+        let Some(table) = self.table else {
+            return;
+        };
+        match self.version {
+            Version::Psf1 => {
+                // u16 entries, 0xFFFE starts the sequences, 0xFFFF ends a glyph
+                let mut entries = table.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+                for glyph in 0..self.glyph_count {
+                    let mut in_sequences = false;
+                    for entry in entries.by_ref() {
+                        match entry {
+                            0xffff => break,
+                            0xfffe => in_sequences = true,
+                            _ if in_sequences => {}
+                            _ => {
+                                if char::from_u32(entry as u32).is_some_and(|c| f(c, glyph)) {
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Version::Psf2 => {
+                // UTF-8 chars, 0xFE starts the sequences, 0xFF ends a glyph
+                for (glyph, entry) in table.split(|&b| b == 0xff).take(self.glyph_count).enumerate() {
+                    let singles = entry.split(|&b| b == 0xfe).next().unwrap_or(&[]);
+                    let Ok(chars) = core::str::from_utf8(singles) else {
+                        continue;
+                    };
+                    if chars.chars().any(|c| f(c, glyph)) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> FrameBufferWriter<'a> {
+    /// Draws text from now on with `font` instead of the built-in one. The
+    /// char cells take on the font's glyph size.
+    pub fn set_font(&mut self, font: PsfFont<'a>) {
+        self.font = Some(font);
+    }
+
+    /// Goes back to the built-in font.
+    pub fn clear_font(&mut self) {
+        self.font = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::fmt::Write;
+    use std::vec::Vec;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::{PsfError, PsfFont, PSF1_MAGIC, PSF2_HAS_TABLE, PSF2_MAGIC};
+
+    /// A PSF2 font of 8x8 glyphs, followed by `table`. Glyph `i` has row
+    /// `i % 8` set, except 'A' (65), which is solid.
+    fn psf2(glyph_count: u32, flags: u32, table: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::from(PSF2_MAGIC);
+        for field in [0, 32, flags, glyph_count, 8, 8, 8] {
+            bytes.extend(u32::to_le_bytes(field));
+        }
+        for glyph in 0..glyph_count as usize {
+            bytes.extend((0..8).map(|row| if glyph == 65 || row == glyph % 8 { 0xff } else { 0 }));
+        }
+        bytes.extend(table);
+        bytes
+    }
+
+    #[test]
+    fn parses_psf2() {
+        let bytes = psf2(128, 0, &[]);
+        let font = PsfFont::parse(&bytes).unwrap();
+        assert_eq!((font.width(), font.height(), font.glyph_count()), (8, 8, 128));
+        assert!(font.contains('A') && !font.contains('é'));
+    }
+
+    #[test]
+    fn parses_psf1() {
+        let mut bytes = Vec::from(PSF1_MAGIC);
+        bytes.extend([0, 16]);
+        bytes.resize(4 + 256 * 16, 0);
+        let font = PsfFont::parse(&bytes).unwrap();
+        assert_eq!((font.width(), font.height(), font.glyph_count()), (8, 16, 256));
+        assert!(font.contains('é'));
+        assert_eq!(PsfFont::parse(&bytes[..100]).err(), Some(PsfError::Truncated));
+    }
+
+    #[test]
+    fn looks_chars_up_in_the_table() {
+        // Glyph 0 shows 'B' and 'b', glyph 1 'é' and the sequence "e\u{301}".
+        let table = [&b"Bb\xff"[..], "é".as_bytes(), b"\xfe", "e\u{301}".as_bytes(), b"\xff"].concat();
+        let bytes = psf2(2, PSF2_HAS_TABLE, &table);
+        let font = PsfFont::parse(&bytes).unwrap();
+        assert!(font.contains('B') && font.contains('b') && font.contains('é'));
+        assert!(!font.contains('e') && !font.contains('A'));
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert_eq!(PsfFont::parse(b"not a font").err(), Some(PsfError::BadMagic));
+        assert_eq!(PsfFont::parse(&PSF2_MAGIC).err(), Some(PsfError::Truncated));
+        let bytes = psf2(128, 0, &[]);
+        assert_eq!(PsfFont::parse(&bytes[..bytes.len() - 1]).err(), Some(PsfError::Truncated));
+        let mut no_width = bytes.clone();
+        no_width[28..32].fill(0);
+        assert_eq!(PsfFont::parse(&no_width).err(), Some(PsfError::BadHeader));
+    }
+
+    #[test]
+    fn draws_with_the_font() {
+        let bytes = psf2(128, 0, &[]);
+        let font = PsfFont::parse(&bytes).unwrap();
+        let mut fb = MockFrameBuffer::<{ 80 * 40 * 4 }>::new(PixelFormat::Rgb, 80, 40);
+        let mut writer = fb.writer();
+        writer.set_font(font);
+        writer.write_str("A").unwrap();
+        assert_eq!(writer.cursor(), (1 + 8, 1));
+        assert!((1..9).all(|y| (1..9).all(|x| writer.read_pixel_bytes(x, y) == Some(writer.pixel_bytes([255; 3])))));
+        writer.clear_font();
+        writer.write_str("A").unwrap();
+        assert_eq!(writer.cursor(), (1 + 8 + super::super::font_constants::CHAR_RASTER_WIDTH, 1));
+    }
+}
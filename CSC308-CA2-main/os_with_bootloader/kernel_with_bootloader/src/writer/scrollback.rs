@@ -0,0 +1,207 @@
+use core::cmp::Ordering;
+
+use super::cell::{Cell, Row, MAX_COLUMNS};
+use super::FrameBufferWriter;
+
+/// How many finished text rows are kept for scrolling back.
+pub const SCROLLBACK_LINES: usize = 64;
+
+/// Ring buffer of the most recent finished text rows.
+///
+/// Rows are numbered by when they were finished, counting from 0. Only the
+/// last [SCROLLBACK_LINES] of them are kept.
+pub struct Scrollback {
+    rows: [Row; SCROLLBACK_LINES],
+    total: usize,
+}
+
+impl Scrollback {
+    pub const fn new() -> Self {
+        Self {
+            rows: [[Cell::BLANK; MAX_COLUMNS]; SCROLLBACK_LINES],
+            total: 0,
+        }
+    }
+
+    /// Forgets every row.
+    pub fn clear(&mut self) {
+        self.total = 0;
+    }
+
+    pub fn push(&mut self, row: &Row) {
+        self.rows[self.total % SCROLLBACK_LINES] = *row;
+        self.total += 1;
+    }
+
+    /// Number of rows pushed so far, including those no longer kept.
+    /// This is also the number the next pushed row will get.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Returns row number `index`, if it is still kept.
+    pub fn row(&self, index: usize) -> Option<&Row> {
+        if index < self.total && index + SCROLLBACK_LINES >= self.total {
+            Some(&self.rows[index % SCROLLBACK_LINES])
+        } else {
+            None
+        }
+    }
+
+    /// Number of rows that are kept.
+    pub fn len(&self) -> usize {
+        self.total.min(SCROLLBACK_LINES)
+    }
+}
+
+/// Scrolling through text that has already left the screen.
+impl FrameBufferWriter<'_> {
+    /// Shows the text as it was `lines` rows further up. Writing anything
+    /// returns to the live view first.
+    pub fn scroll_back(&mut self, lines: usize) {
+        // The rows above the cursor are in the scrollback too, but still on
+        // screen, so scrolling back past the others would only show blanks.
+        let (_, cursor_row) = self.cursor_cell();
+        let limit = self.scrollback.len().saturating_sub(cursor_row);
+        self.scroll_offset = self.scroll_offset.saturating_add(lines).min(limit);
+        self.repaint_history();
+    }
+
+    /// Moves the view `lines` rows back towards the live view.
+    pub fn scroll_forward(&mut self, lines: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+        self.repaint_history();
+    }
+
+    /// Whether the screen shows the latest text, i.e. isn't scrolled back.
+    pub fn is_live(&self) -> bool {
+        self.scroll_offset == 0
+    }
+
+    /// Returns to the live view if scrolled back.
+    pub(super) fn scroll_to_live(&mut self) {
+        if !self.is_live() {
+            self.scroll_offset = 0;
+            self.repaint_history();
+        }
+    }
+
+    /// Copies the current row into the scrollback, once it is finished.
+    pub(super) fn finish_row(&mut self) {
+        if !self.record_history {
+            return;
+        }
+        let (_, row) = self.cursor_cell();
+        match self.cells.get(row) {
+            Some(cells) => self.scrollback.push(cells),
+            None => self.scrollback.push(&[Cell::BLANK; MAX_COLUMNS]),
+        }
+    }
+
+    /// Redraws every text row on the screen, shifted down by the scroll
+    /// offset. The rows above the cursor come from the scrollback.
+    fn repaint_history(&mut self) {
+        if self.is_live() {
+            self.repaint();
+            return;
+        }
+        let line_height = self.line_height();
+        let (_, cursor_row) = self.cursor_cell();
+        let current_index = self.scrollback.total();
+        for screen_row in 0..self.screen_rows() {
+            let y = self.text_top() + screen_row * line_height;
+            let (x, _, width, _) = self.text_area();
+            self.draw_filled_rect(x, y, width, line_height, self.bg_color);
+
+            // Index (as in Scrollback::row) of the text row that goes here,
+            // where the cursor's row gets the next free index
+            let Some(index) = (current_index + screen_row).checked_sub(cursor_row + self.scroll_offset)
+            else {
+                continue;
+            };
+            let row = match index.cmp(&current_index) {
+                Ordering::Less => match self.scrollback.row(index) {
+                    Some(row) => *row,
+                    None => continue,
+                },
+                // rows at and below the cursor row are still on screen
+                _ => match self.cells.get(cursor_row + index - current_index) {
+                    Some(row) => *row,
+                    None => continue,
+                },
+            };
+            self.draw_row(y, &row);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::cell::{Cell, MAX_COLUMNS};
+    use super::super::mock::MockFrameBuffer;
+    use super::{Scrollback, SCROLLBACK_LINES};
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+
+    type Mock = MockFrameBuffer<{ WIDTH * HEIGHT * 4 }>;
+
+    fn mock() -> Mock {
+        Mock::new(PixelFormat::Rgb, WIDTH, HEIGHT)
+    }
+
+    fn same_pixels(a: &Mock, b: &Mock) -> bool {
+        (0..HEIGHT).all(|y| (0..WIDTH).all(|x| a.pixel_at(x, y) == b.pixel_at(x, y)))
+    }
+
+    #[test]
+    fn keeps_the_latest_rows() {
+        let mut scrollback = Scrollback::new();
+        let mut row = [Cell::BLANK; MAX_COLUMNS];
+        for i in 0..SCROLLBACK_LINES + 3 {
+            row[0].ch = char::from_digit(i as u32 % 10, 10).unwrap();
+            scrollback.push(&row);
+        }
+        assert_eq!((scrollback.total(), scrollback.len()), (SCROLLBACK_LINES + 3, SCROLLBACK_LINES));
+        assert!(scrollback.row(2).is_none());
+        assert_eq!(scrollback.row(3).map(|row| row[0].ch), Some('3'));
+        assert_eq!(scrollback.row(SCROLLBACK_LINES + 2).map(|row| row[0].ch), Some('6'));
+        assert!(scrollback.row(SCROLLBACK_LINES + 3).is_none());
+        scrollback.clear();
+        assert_eq!(scrollback.len(), 0);
+    }
+
+    #[test]
+    fn scrolls_back_to_old_lines() {
+        let mut expected = mock();
+        expected.writer().write_str("l0\nl1").unwrap();
+        let mut live = mock();
+        live.writer().write_str("l1\nl2").unwrap();
+
+        let mut fb = mock();
+        let mut writer = fb.writer();
+        writer.write_str("l0\nl1\nl2").unwrap();
+        writer.scroll_back(1);
+        assert!(!writer.is_live());
+        assert!(same_pixels(&fb, &expected));
+
+        let mut writer = fb.writer();
+        writer.write_str("l0\nl1\nl2").unwrap();
+        writer.scroll_back(99);
+        writer.scroll_forward(1);
+        assert!(writer.is_live());
+        assert!(same_pixels(&fb, &live));
+
+        // Writing goes back to the live view first.
+        let mut writer = fb.writer();
+        writer.write_str("l0\nl1\nl").unwrap();
+        writer.scroll_back(1);
+        writer.write_str("2").unwrap();
+        assert!(writer.is_live());
+        assert!(same_pixels(&fb, &live));
+    }
+}
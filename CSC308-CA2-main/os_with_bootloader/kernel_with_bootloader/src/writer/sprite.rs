@@ -0,0 +1,128 @@
+use super::{FrameBufferWriter, WrongPixelCount};
+
+/// Pixels of this color in a sprite are left out, so the screen shows
+/// through.
+pub const SPRITE_TRANSPARENT: [u8; 3] = [255, 0, 255];
+
+/// Widest and highest sprite that is drawn in full; bigger ones are cut off.
+pub const MAX_SPRITE_SIZE: usize = 32;
+
+/// The screen contents under a shown sprite, to put back when it's hidden.
+pub(super) struct SpriteBackup {
+    pixels: [[u8; 4]; MAX_SPRITE_SIZE * MAX_SPRITE_SIZE],
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+    shown: bool,
+}
+
+impl SpriteBackup {
+    pub(super) const fn new() -> Self {
+        Self {
+            pixels: [[0; 4]; MAX_SPRITE_SIZE * MAX_SPRITE_SIZE],
+            x: 0,
+            y: 0,
+            w: 0,
+            h: 0,
+            shown: false,
+        }
+    }
+}
+
+/// A sprite, e.g. a mouse pointer, drawn over the screen without destroying
+/// what's under it.
+impl FrameBufferWriter<'_> {
+    /// Draws a `w` x `h` sprite, given as rows of RGB pixels like for
+    /// [Self::blit], with its top left corner at (x, y). Pixels in
+    /// [SPRITE_TRANSPARENT] are skipped. A sprite already shown is hidden
+    /// first, so calling this again moves it.
+    ///
+    /// Anything drawn under the sprite while it's shown is lost when it's
+    /// hidden again.
+    pub fn show_cursor_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        sprite: &[[u8; 3]],
+        w: usize,
+        h: usize,
+    ) -> Result<(), WrongPixelCount> {
+        if sprite.len() != w * h {
+            return Err(WrongPixelCount { expected: w * h, actual: sprite.len() });
+        }
+        self.hide_cursor_sprite();
+        let (shown_w, shown_h) = (w.min(MAX_SPRITE_SIZE), h.min(MAX_SPRITE_SIZE));
+        for row in 0..shown_h {
+            for col in 0..shown_w {
+                let saved = self.read_pixel_bytes(x.saturating_add(col), y.saturating_add(row));
+                self.sprite_backup.pixels[row * MAX_SPRITE_SIZE + col] = saved.unwrap_or([0; 4]);
+            }
+        }
+        let backup = &mut self.sprite_backup;
+        (backup.x, backup.y, backup.w, backup.h, backup.shown) = (x, y, shown_w, shown_h, true);
+        for row in 0..shown_h {
+            for col in 0..shown_w {
+                let color = sprite[row * w + col];
+                if color != SPRITE_TRANSPARENT {
+                    self.set_pixel(x.saturating_add(col), y.saturating_add(row), color);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts back what was under the sprite. Does nothing if it isn't shown.
+    pub fn hide_cursor_sprite(&mut self) {
+        if !self.sprite_backup.shown {
+            return;
+        }
+        self.sprite_backup.shown = false;
+        let SpriteBackup { x, y, w, h, .. } = self.sprite_backup;
+        for row in 0..h {
+            for col in 0..w {
+                let saved = self.sprite_backup.pixels[row * MAX_SPRITE_SIZE + col];
+                self.write_pixel_bytes(x.saturating_add(col), y.saturating_add(row), saved);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::{FrameBufferWriter, WrongPixelCount};
+    use super::SPRITE_TRANSPARENT;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+    const RED: [u8; 3] = [255, 0, 0];
+    const GREEN: [u8; 3] = [0, 255, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    fn is(writer: &FrameBufferWriter, x: usize, y: usize, color: [u8; 3]) -> bool {
+        writer.read_pixel_bytes(x, y) == Some(writer.pixel_bytes(color))
+    }
+
+    #[test]
+    fn hiding_puts_the_screen_back() {
+        let sprite = [RED, SPRITE_TRANSPARENT, RED, RED];
+        let mut fb = MockFrameBuffer::<{ WIDTH * HEIGHT * 4 }>::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let mut writer = fb.writer();
+        writer.fill_screen(BLUE);
+        writer.set_pixel(10, 10, GREEN);
+        let before = writer.checksum();
+        assert_eq!(writer.show_cursor_sprite(10, 10, &sprite, 2, 2), Ok(()));
+        assert_eq!(writer.show_cursor_sprite(0, 0, &sprite, 3, 2), Err(WrongPixelCount { expected: 6, actual: 4 }));
+        assert!(is(&writer, 10, 10, RED) && is(&writer, 11, 10, BLUE) && is(&writer, 11, 11, RED));
+
+        // Showing it again moves it, partly off screen.
+        writer.show_cursor_sprite(WIDTH - 1, HEIGHT - 1, &sprite, 2, 2).unwrap();
+        assert!(is(&writer, 10, 10, GREEN) && is(&writer, WIDTH - 1, HEIGHT - 1, RED));
+        writer.hide_cursor_sprite();
+        writer.hide_cursor_sprite();
+        assert_eq!(writer.checksum(), before);
+    }
+}
@@ -0,0 +1,63 @@
+use core::fmt::{self, Write};
+
+use spin::{Mutex, MutexGuard};
+
+use super::FrameBufferWriter;
+
+/// A [FrameBufferWriter] behind a spinlock, so it can be shared, e.g. from a
+/// `static`. Every access goes through [Self::lock], which keeps two users
+/// from drawing at the same time.
+pub struct SyncWriter<'a>(pub Mutex<FrameBufferWriter<'a>>);
+
+impl<'a> SyncWriter<'a> {
+    pub const fn new(writer: FrameBufferWriter<'a>) -> Self {
+        Self(Mutex::new(writer))
+    }
+
+    /// Waits until no one else is using the writer and takes it.
+    pub fn lock(&self) -> MutexGuard<'_, FrameBufferWriter<'a>> {
+        self.0.lock()
+    }
+
+    /// Writes `s` while holding the lock.
+    pub fn print(&self, s: &str) {
+        let _ = self.lock().write_str(s);
+    }
+
+    /// Writes formatted text while holding the lock. Makes `write!` work on a
+    /// shared reference.
+    pub fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        self.lock().write_fmt(args)
+    }
+}
+
+/// Lets a shared writer be used wherever a [Write] is expected, e.g. inside a
+/// [super::LineBuffered]. Each call takes the lock once.
+impl Write for &SyncWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.lock().write_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::super::TextPosition;
+    use super::SyncWriter;
+
+    #[test]
+    fn shared_writes_go_through_the_lock() {
+        let mut fb = MockFrameBuffer::<{ 80 * 40 * 4 }>::new(PixelFormat::Rgb, 80, 40);
+        let writer = SyncWriter::new(fb.writer());
+        writer.print("a");
+        write!(writer, "{}", 1).unwrap();
+        (&writer).write_str("b").unwrap();
+        assert_eq!(writer.lock().find("a1b"), Some(TextPosition::Screen { col: 0, row: 0 }));
+        // The lock is given back after every write.
+        assert!(writer.0.try_lock().is_some());
+    }
+}
@@ -0,0 +1,118 @@
+use super::FrameBufferWriter;
+
+/// Keeping the text inside a rectangle of the screen, e.g. next to a side
+/// panel. Without a text area set, the text uses the whole screen.
+impl FrameBufferWriter<'_> {
+    /// Keeps all text from now on inside the `w` x `h` rectangle whose top
+    /// left corner is at (x, y), clipped to the screen: lines start at its
+    /// left edge (plus the border padding) and wrap and scroll at its
+    /// bottom right. Clears the area and moves the cursor to its start. The
+    /// area outside is left alone and can still be drawn to, e.g. with
+    /// [Self::draw_filled_rect]. Empty areas are ignored.
+    pub fn set_text_area(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        if x >= self.width() || y >= self.height() || w == 0 || h == 0 {
+            return;
+        }
+        self.flush_word();
+        self.scroll_to_live();
+        self.text_area = Some((x, y, w, h));
+        self.scroll_region = None;
+        self.saved_cursor = None;
+        self.previous_line_end = None;
+        self.clear();
+    }
+
+    /// Lets the text use the whole screen again and clears it.
+    pub fn reset_text_area(&mut self) {
+        self.flush_word();
+        self.scroll_to_live();
+        self.text_area = None;
+        self.scroll_region = None;
+        self.saved_cursor = None;
+        self.previous_line_end = None;
+        self.clear();
+    }
+
+    /// The text area as (x, y, w, h), clipped to the screen; the whole
+    /// screen if none is set.
+    pub(super) fn text_area(&self) -> (usize, usize, usize, usize) {
+        let (width, height) = (self.width(), self.height());
+        match self.text_area {
+            Some((x, y, w, h)) => {
+                let (x, y) = (x.min(width), y.min(height));
+                (x, y, w.min(width - x), h.min(height - y))
+            }
+            None => (0, 0, width, height),
+        }
+    }
+
+    /// Where lines start.
+    pub(super) fn text_left(&self) -> usize {
+        self.text_area().0 + self.border_padding
+    }
+
+    /// Where the first line starts.
+    pub(super) fn text_top(&self) -> usize {
+        self.text_area().1 + self.border_padding
+    }
+
+    /// The right edge of the text area, excluded.
+    pub(super) fn text_right(&self) -> usize {
+        let (x, _, w, _) = self.text_area();
+        x + w
+    }
+
+    /// The bottom edge of the text area, excluded.
+    pub(super) fn text_bottom(&self) -> usize {
+        let (_, y, _, h) = self.text_area();
+        y + h
+    }
+
+    /// `h` cut down so that a rectangle starting at row `y` doesn't reach
+    /// past the bottom of the text area, as one on the line after the last
+    /// row would.
+    pub(super) fn clip_to_text_bottom(&self, y: usize, h: usize) -> usize {
+        h.min(self.text_bottom().saturating_sub(y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 60;
+    const RED: [u8; 3] = [255, 0, 0];
+
+    #[test]
+    fn text_stays_inside_the_area() {
+        let (x, y, w, h) = (20, 10, 40, 40);
+        let mut fb = MockFrameBuffer::<{ WIDTH * HEIGHT * 4 }>::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let mut writer = fb.writer();
+        writer.fill_screen(RED);
+        writer.set_text_area(x, y, w, h);
+        assert_eq!(writer.cursor(), (x + 1, y + 1));
+        writer.draw_cursor();
+        writer.write_str("text that wraps\nand scrolls\n\x1b[2Kand more\n\x1b[K\x1b[1K\x1b[L\x1b[J").unwrap();
+        // Empty areas don't replace it.
+        writer.set_text_area(WIDTH, 0, 10, 10);
+        writer.set_text_area(0, 0, 0, 10);
+        assert!(writer.cursor().0 > x);
+        for py in 0..HEIGHT {
+            for px in 0..WIDTH {
+                if !((x..x + w).contains(&px) && (y..y + h).contains(&py)) {
+                    assert_eq!(fb.pixel_at(px, py), RED, "({}, {}) was drawn to", px, py);
+                }
+            }
+        }
+
+        let mut writer = fb.writer();
+        writer.set_text_area(x, y, w, h);
+        writer.reset_text_area();
+        assert_eq!(writer.cursor(), (1, 1));
+    }
+}
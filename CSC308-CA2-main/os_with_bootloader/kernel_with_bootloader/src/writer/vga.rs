@@ -0,0 +1,172 @@
+//! A stand-in for the legacy VGA text buffer at `0xB8000`, for code written
+//! against its char + attribute byte cells.
+
+use super::{colors::rgb, FrameBufferWriter, TextAttrs};
+
+/// Size of the VGA text mode grid.
+pub const VGA_COLUMNS: usize = 80;
+pub const VGA_ROWS: usize = 25;
+
+/// The 16 colors of the VGA text mode palette, in attribute nibble order.
+pub const VGA_COLORS: [[u8; 3]; 16] = [
+    rgb(0x000000),
+    rgb(0x0000aa),
+    rgb(0x00aa00),
+    rgb(0x00aaaa),
+    rgb(0xaa0000),
+    rgb(0xaa00aa),
+    rgb(0xaa5500),
+    rgb(0xaaaaaa),
+    rgb(0x555555),
+    rgb(0x5555ff),
+    rgb(0x55ff55),
+    rgb(0x55ffff),
+    rgb(0xff5555),
+    rgb(0xff55ff),
+    rgb(0xffff55),
+    rgb(0xffffff),
+];
+
+/// The char each byte of code page 437, the VGA character set, shows.
+const CP437: [char; 256] = [
+    ' ', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+    ' ', '!', '"', '#', '$', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+    '@', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '[', '\\', ']', '^', '_',
+    '`', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '{', '|', '}', '~', '⌂',
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Splits a VGA attribute byte into its foreground and background colors.
+/// The low nibble is the foreground; bits 4 to 6 are the background. Bit 7,
+/// blink on real hardware, is ignored.
+pub fn vga_attr_colors(attr: u8) -> ([u8; 3], [u8; 3]) {
+    (VGA_COLORS[(attr & 0x0f) as usize], VGA_COLORS[(attr >> 4 & 0x07) as usize])
+}
+
+/// Maps an 80x25 grid of VGA cells onto the writer's own char cells, starting
+/// from the top left one. Cells that don't fit the screen are dropped. What is
+/// written this way shows up in [FrameBufferWriter::char_at] and survives a
+/// repaint, like any other text.
+pub struct VgaCompat<'w, 'a> {
+    writer: &'w mut FrameBufferWriter<'a>,
+}
+
+impl<'w, 'a> VgaCompat<'w, 'a> {
+    pub fn new(writer: &'w mut FrameBufferWriter<'a>) -> Self {
+        Self { writer }
+    }
+
+    /// Writes one VGA cell: `ch` is a code page 437 byte and `attr` the VGA
+    /// color attribute (see [vga_attr_colors]). Leaves the writer's colors,
+    /// attributes and streaming cursor as they were. Cells outside 80x25 are
+    /// ignored.
+    pub fn write_cell(&mut self, col: usize, row: usize, ch: u8, attr: u8) {
+        if col >= VGA_COLUMNS || row >= VGA_ROWS {
+            return;
+        }
+        let writer = &mut *self.writer;
+        if col >= writer.screen_columns() || row >= writer.screen_rows() {
+            return;
+        }
+        let (fg, bg) = vga_attr_colors(attr);
+        let saved = (
+            writer.x_pos,
+            writer.y_pos,
+            writer.text_color,
+            writer.bg_color,
+            writer.opaque_background,
+            writer.reverse,
+            writer.text_attrs,
+        );
+        (writer.x_pos, writer.y_pos) = writer.cell_origin(col, row);
+        writer.text_color = fg;
+        writer.bg_color = bg;
+        writer.opaque_background = true;
+        writer.reverse = false;
+        writer.text_attrs = TextAttrs::empty();
+        let c = CP437[ch as usize];
+        writer.record_cell(c);
+        writer.draw_char(c);
+        (
+            writer.x_pos,
+            writer.y_pos,
+            writer.text_color,
+            writer.bg_color,
+            writer.opaque_background,
+            writer.reverse,
+            writer.text_attrs,
+        ) = saved;
+    }
+
+    /// Fills the whole grid with blanks in the colors of `attr`, like writing
+    /// `0x20` to every cell.
+    pub fn clear(&mut self, attr: u8) {
+        for row in 0..VGA_ROWS {
+            for col in 0..VGA_COLUMNS {
+                self.write_cell(col, row, b' ', attr);
+            }
+        }
+    }
+}
+
+impl<'a> FrameBufferWriter<'a> {
+    /// Borrows the writer as a VGA text buffer, see [VgaCompat].
+    pub fn vga(&mut self) -> VgaCompat<'_, 'a> {
+        VgaCompat::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use bootloader_api::info::PixelFormat;
+
+    use super::super::mock::MockFrameBuffer;
+    use super::{vga_attr_colors, VGA_COLORS};
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 40;
+
+    #[test]
+    fn splits_attributes() {
+        assert_eq!(vga_attr_colors(0x1f), (VGA_COLORS[15], VGA_COLORS[1]));
+        // The blink bit is ignored.
+        assert_eq!(vga_attr_colors(0x9f), vga_attr_colors(0x1f));
+        assert_eq!(VGA_COLORS[15], [255; 3]);
+    }
+
+    #[test]
+    fn cells_become_writer_text() {
+        let mut fb = MockFrameBuffer::<{ WIDTH * HEIGHT * 4 }>::new(PixelFormat::Rgb, WIDTH, HEIGHT);
+        let mut writer = fb.writer();
+        writer.write_str("ab").unwrap();
+        let (cursor, colors) = (writer.cursor(), (writer.text_color, writer.bg_color));
+        let mut vga = writer.vga();
+        vga.write_cell(2, 1, b'A', 0x1f);
+        vga.write_cell(3, 1, 0xdb, 0x07);
+        vga.write_cell(79, 24, b'Z', 0x07);
+        vga.write_cell(80, 0, b'Z', 0x07);
+        assert_eq!((writer.char_at(2, 1), writer.char_at(3, 1)), (Some('A'), Some('█')));
+        assert_eq!((writer.cells[1][2].fg, writer.cells[1][2].bg), vga_attr_colors(0x1f));
+        assert_eq!((writer.cursor(), (writer.text_color, writer.bg_color)), (cursor, colors));
+        let expected = writer.checksum();
+        writer.repaint();
+        assert_eq!(writer.checksum(), expected);
+
+        writer.vga().clear(0x10);
+        assert_eq!(writer.char_at(2, 1), Some(' '));
+        assert_eq!(writer.cells[0][0].bg, VGA_COLORS[1]);
+    }
+}